@@ -0,0 +1,179 @@
+//! Times the CPU renderer, which needs no GPU or window, at a few resolutions and views of
+//! a representative map, from the spawn of a generated one, and along the benchmark scene's
+//! camera path. Run with `cargo bench`; pass a substring to run matching cases only.
+//!
+//! The scene path cycles through every keyframe in assets/bench/camera_path.txt, one per
+//! frame, so it covers near walls, the long corridor, the sprite-filled pillar room, windows
+//! and the lit, water-floored corner together. On one core of a Xeon server a release
+//! build took about 11 ms a frame at 640x360, 40 ms at 1280x720 and 100 ms at 1920x1080.
+//! Expect roughly that spread elsewhere, with frame time growing with the pixel count; a
+//! jump well outside it between commits is worth looking into.
+//!
+//! The scene path is timed again on a single thread, to show what spreading the columns
+//! over every core gains.
+//!
+//! The sprite cases crowd the far end of the open top row with DISTANT_SPRITES sprites and
+//! draw them textured, then as blocks with the sprite LOD, to show what it saves. On the same
+//! machine the blocks took about 8% off a 1920x1080 frame, 61 ms to 56 ms, and at 640x360
+//! the sprites cover too few pixels for it to show.
+
+use std::{
+    cell::RefCell,
+    hint::black_box,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use cgmath::Vector2;
+use rayon::ThreadPoolBuilder;
+use rust_doom::{
+    camera::{self, Camera},
+    generate::generate,
+    map::{Map, Sprite},
+    renderer::Renderer,
+};
+use winit::dpi::PhysicalSize;
+
+const MAP: &str = "assets/maps/large.map";
+const SCENE: &str = "assets/bench/scene.map";
+const SCENE_PATH: &str = "assets/bench/camera_path.txt";
+const RESOLUTIONS: [(u32, u32); 3] = [(640, 360), (1280, 720), (1920, 1080)];
+/// Named camera positions and facing directions.
+const VIEWS: [(&str, [f32; 2], [f32; 2]); 3] = [
+    ("spawn", [17.5, 17.5], [1., 0.]),
+    ("diagonal", [1.5, 1.5], [1., 1.]),
+    // Down the open top row, so the middle rays cross the whole map before they hit a wall
+    ("full length", [1.5, 1.5], [1., 0.]),
+];
+/// How many sprites the sprite cases scatter over the far end of the top two rows, seen
+/// from "full length", and the LOD distance that draws every one of them as a block.
+const DISTANT_SPRITES: usize = 200;
+const SPRITE_LOD_DISTANCE: f32 = 8.;
+/// Size, wall density and seed of the generated map, which is viewed from its spawn.
+const GENERATED: (usize, usize, f32, u64) = (128, 128, 0.1, 1);
+const WARMUP_FRAMES: u32 = 5;
+/// How long each case is timed for, and the fewest frames it's averaged over.
+const MEASURE_TIME: Duration = Duration::from_secs(1);
+const MIN_FRAMES: u32 = 10;
+
+fn main() -> Result<()> {
+    // Cargo passes `--bench` and any filter after `--`
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    let skip = |name: &str| filter.as_ref().is_some_and(|filter| !name.contains(filter));
+    let root = env!("CARGO_MANIFEST_DIR");
+    std::env::set_current_dir(root)?;
+    let map = Map::load(MAP)?;
+    let (map_width, map_height, density, seed) = GENERATED;
+    let generated = generate(map_width, map_height, density, seed)?;
+    let scene = Map::load(SCENE)?;
+    let scene_path = camera::parse_path(&std::fs::read_to_string(SCENE_PATH)?)?;
+    // Each case's map and the keyframes its frames cycle through
+    let cases = VIEWS
+        .iter()
+        .map(|&(view, position, direction)| {
+            let keyframes = vec![(Vector2::from(position), Vector2::from(direction))];
+            (view, &map, keyframes)
+        })
+        .chain([
+            (
+                "generated",
+                &generated,
+                vec![(generated.spawn_pos(), generated.spawn_dir())],
+            ),
+            ("scene path", &scene, scene_path.clone()),
+        ]);
+    let serial = ThreadPoolBuilder::new().num_threads(1).build()?;
+
+    for (width, height) in RESOLUTIONS {
+        for (view, map, keyframes) in cases.clone() {
+            let name = format!("{width}x{height} {view}");
+            if !skip(&name) {
+                time_path(&name, map, &keyframes, width, height)?;
+            }
+        }
+        let name = format!("{width}x{height} scene path serial");
+        if !skip(&name) {
+            serial.install(|| time_path(&name, &scene, &scene_path, width, height))?;
+        }
+
+        for (lod, lod_distance) in [("textured", f32::INFINITY), ("LOD", SPRITE_LOD_DISTANCE)] {
+            let name = format!("{width}x{height} sprites {lod}");
+            if skip(&name) {
+                continue;
+            }
+            let (_, position, direction) = VIEWS[2];
+            let aspect = width as f32 / height as f32;
+            let camera = Camera::new(position.into(), direction.into(), 0.5 * aspect);
+            let map = Rc::new(RefCell::new(map.clone()));
+            let mut renderer = Renderer::new(
+                Rc::new(RefCell::new(camera)),
+                map,
+                PhysicalSize::new(width, height),
+            )?;
+            renderer.set_ray_caching(false);
+            renderer.set_sprite_lod_distance(lod_distance);
+            // 20 across and 10 deep, 12 to 30 tiles away
+            renderer.clear_sprites();
+            for i in 0..DISTANT_SPRITES {
+                renderer.add_sprite(Sprite {
+                    position: Vector2::new(
+                        12. + (i % 20) as f32 * 0.9,
+                        1.1 + (i / 20) as f32 * 0.18,
+                    ),
+                    texture: i % 2,
+                });
+            }
+            time(&name, |_| {
+                renderer.render();
+                black_box(renderer.pixels());
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Times rendering `map` at `width` by `height`, cycling through a camera at each keyframe.
+fn time_path(
+    name: &str,
+    map: &Map,
+    keyframes: &[(Vector2<f32>, Vector2<f32>)],
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let aspect = width as f32 / height as f32;
+    let cameras: Vec<Camera> = keyframes
+        .iter()
+        .map(|&(position, direction)| Camera::new(position, direction, 0.5 * aspect))
+        .collect();
+    let camera = Rc::new(RefCell::new(cameras[0]));
+    let map = Rc::new(RefCell::new(map.clone()));
+    let mut renderer = Renderer::new(camera.clone(), map, PhysicalSize::new(width, height))?;
+    // The views never move, so cached rays would leave the wall pass untimed
+    renderer.set_ray_caching(false);
+    time(name, |frame| {
+        *camera.borrow_mut() = cameras[frame as usize % cameras.len()];
+        renderer.render();
+        black_box(renderer.pixels());
+    });
+    Ok(())
+}
+
+/// Calls `render` with each frame's number, warming up and then for at least MEASURE_TIME
+/// and MIN_FRAMES, and prints the average time a frame took.
+fn time(name: &str, mut render: impl FnMut(u32)) {
+    for frame in 0..WARMUP_FRAMES {
+        render(frame);
+    }
+    let start = Instant::now();
+    let mut frames = 0;
+    while frames < MIN_FRAMES || start.elapsed() < MEASURE_TIME {
+        render(frames);
+        frames += 1;
+    }
+    let per_frame = start.elapsed() / frames;
+    println!(
+        "{name:<24} {:>12} ns/frame ({frames} frames)",
+        per_frame.as_nanos()
+    );
+}
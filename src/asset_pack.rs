@@ -0,0 +1,305 @@
+use std::{collections::HashMap, fs, io::Read, path::Path};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::DeflateDecoder;
+use image::RgbaImage;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+/// The end of central directory record, before its variable-length comment.
+const END_RECORD_LEN: usize = 22;
+/// How far from the end the record can start: its own length plus the longest comment.
+const END_SEARCH_LEN: usize = END_RECORD_LEN + u16::MAX as usize;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// The most space reserved up front for a file, whatever size the archive claims it is.
+/// Larger files grow as they're read.
+const MAX_RESERVED: usize = 1 << 20;
+
+/// Where one file's data sits in the archive, as its central directory entry says.
+struct Entry {
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+/// Assets bundled into a single zip archive, looked up by their path inside it. Entries are
+/// decompressed when they're read rather than up front.
+///
+/// Only what's needed to read archives from ordinary tools is supported: stored and
+/// deflated entries, without encryption or the zip64 extensions for files over 4 GiB.
+pub struct AssetPack {
+    /// Where the archive came from, for error messages.
+    source: String,
+    data: Vec<u8>,
+    entries: HashMap<String, Entry>,
+}
+
+impl AssetPack {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path)
+            .with_context(|| format!("failed to read asset pack {}", path.display()))?;
+        Self::from_bytes(path.display().to_string(), data)
+    }
+
+    /// Reads the archive's central directory from `data`. `source` names the archive in
+    /// error messages.
+    pub fn from_bytes(source: impl Into<String>, data: Vec<u8>) -> Result<Self> {
+        let source = source.into();
+        let entries =
+            read_directory(&data).with_context(|| format!("failed to read asset pack {source}"))?;
+        Ok(Self {
+            source,
+            data,
+            entries,
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// The paths of every file in the archive, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// The decompressed contents of the file at `name`.
+    pub fn read(&self, name: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .get(name)
+            .with_context(|| format!("asset {name:?} isn't in {}", self.source))?;
+        self.extract(entry)
+            .with_context(|| format!("failed to read asset {name:?} from {}", self.source))
+    }
+
+    /// Decodes the image at `name` into RGBA.
+    pub fn image(&self, name: &str) -> Result<RgbaImage> {
+        let bytes = self.read(name)?;
+        let image = image::load_from_memory(&bytes)
+            .with_context(|| format!("failed to decode asset {name:?} from {}", self.source))?;
+        Ok(image.into_rgba8())
+    }
+
+    fn extract(&self, entry: &Entry) -> Result<Vec<u8>> {
+        let header = entry.header_offset;
+        if read_u32(&self.data, header)? != LOCAL_HEADER_SIGNATURE {
+            bail!("missing local file header");
+        }
+        // The local header repeats the name and may have a different extra field, so its
+        // own lengths say where the data starts
+        let start = header
+            + 30
+            + read_u16(&self.data, header + 26)? as usize
+            + read_u16(&self.data, header + 28)? as usize;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .context("file data runs past the end of the archive")?;
+
+        let contents = match entry.method {
+            STORED => compressed.to_vec(),
+            DEFLATED => {
+                // The size is only as trustworthy as the archive, so it can't be allowed to
+                // reserve or inflate to any more than that. A byte past it is enough to tell
+                // it's wrong
+                let mut contents = Vec::with_capacity(entry.size.min(MAX_RESERVED));
+                DeflateDecoder::new(compressed)
+                    .take(entry.size as u64 + 1)
+                    .read_to_end(&mut contents)
+                    .context("invalid deflate data")?;
+                contents
+            }
+            method => bail!("unsupported compression method {method}"),
+        };
+        if contents.len() != entry.size || crc32fast::hash(&contents) != entry.crc {
+            bail!("data is corrupt");
+        }
+        Ok(contents)
+    }
+}
+
+/// Finds the end of central directory record and reads every file entry it points to.
+/// Directories are left out, since they hold no data.
+fn read_directory(data: &[u8]) -> Result<HashMap<String, Entry>> {
+    // The record ends the archive unless it has a comment, so it's searched for backwards
+    let search_start = data.len().saturating_sub(END_SEARCH_LEN);
+    let end = (search_start..=data.len().saturating_sub(END_RECORD_LEN))
+        .rev()
+        .find(|&i| read_u32(data, i).ok() == Some(END_SIGNATURE))
+        .context("not a zip archive")?;
+    let count = read_u16(data, end + 10)? as usize;
+    let mut offset = read_u32(data, end + 16)? as usize;
+
+    let mut entries = HashMap::with_capacity(count);
+    for _ in 0..count {
+        if read_u32(data, offset)? != CENTRAL_HEADER_SIGNATURE {
+            bail!("corrupt central directory");
+        }
+        let flags = read_u16(data, offset + 8)?;
+        let name_len = read_u16(data, offset + 28)? as usize;
+        let extra_len = read_u16(data, offset + 30)? as usize;
+        let comment_len = read_u16(data, offset + 32)? as usize;
+        let name = data
+            .get(offset + 46..offset + 46 + name_len)
+            .context("corrupt central directory")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        let entry = Entry {
+            method: read_u16(data, offset + 10)?,
+            crc: read_u32(data, offset + 16)?,
+            compressed_size: read_u32(data, offset + 20)? as usize,
+            size: read_u32(data, offset + 24)? as usize,
+            header_offset: read_u32(data, offset + 42)? as usize,
+        };
+        offset += 46 + name_len + extra_len + comment_len;
+
+        if flags & 1 != 0 {
+            bail!("{name:?} is encrypted");
+        }
+        if [entry.compressed_size, entry.size, entry.header_offset].contains(&(u32::MAX as usize)) {
+            bail!("{name:?} needs zip64, which isn't supported");
+        }
+        if !name.ends_with('/') {
+            entries.insert(name, entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("unexpected end of archive")?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("unexpected end of archive")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::DeflateEncoder, Compression};
+
+    use super::*;
+
+    /// A zip archive of `files`, each a name, its contents and how they're compressed.
+    fn zip(files: &[(&str, &[u8], u16)]) -> Vec<u8> {
+        let (mut data, mut directory) = (Vec::new(), Vec::new());
+        for &(name, contents, method) in files {
+            let compressed = match method {
+                DEFLATED => {
+                    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(contents).unwrap();
+                    encoder.finish().unwrap()
+                }
+                _ => contents.to_vec(),
+            };
+            let offset = data.len() as u32;
+            // From the version needed to the extra field length, which both headers share
+            let common = [
+                &20u16.to_le_bytes()[..],
+                &0u16.to_le_bytes(),
+                &method.to_le_bytes(),
+                &[0; 4],
+                &crc32fast::hash(contents).to_le_bytes(),
+                &(compressed.len() as u32).to_le_bytes(),
+                &(contents.len() as u32).to_le_bytes(),
+                &(name.len() as u16).to_le_bytes(),
+                &0u16.to_le_bytes(),
+            ]
+            .concat();
+            data.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            data.extend(&common);
+            data.extend(name.as_bytes());
+            data.extend(&compressed);
+
+            directory.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend(20u16.to_le_bytes());
+            directory.extend(&common);
+            // Comment length, disk, internal and external attributes
+            directory.extend([0; 10]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = data.len() as u32;
+        let count = files.len() as u16;
+        data.extend(&directory);
+        data.extend(END_SIGNATURE.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend(count.to_le_bytes());
+        data.extend(count.to_le_bytes());
+        data.extend((directory.len() as u32).to_le_bytes());
+        data.extend(directory_offset.to_le_bytes());
+        data.extend([0; 2]);
+        data
+    }
+
+    #[test]
+    fn stored_and_deflated_files_read_back() {
+        let text = b"wall textures ".repeat(100);
+        let pack = AssetPack::from_bytes(
+            "test.zip",
+            zip(&[
+                ("stored.txt", b"plain", STORED),
+                ("textures/deflated.txt", &text, DEFLATED),
+                ("empty", b"", DEFLATED),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(pack.read("stored.txt").unwrap(), b"plain");
+        assert_eq!(pack.read("textures/deflated.txt").unwrap(), text);
+        assert_eq!(pack.read("empty").unwrap(), b"");
+        let mut names: Vec<&str> = pack.names().collect();
+        names.sort();
+        assert_eq!(names, ["empty", "stored.txt", "textures/deflated.txt"]);
+    }
+
+    #[test]
+    fn images_are_decoded() {
+        let image = RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8, y as u8, 7, 255]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let pack =
+            AssetPack::from_bytes("test.zip", zip(&[("brick.png", png.get_ref(), DEFLATED)]))
+                .unwrap();
+        assert_eq!(pack.image("brick.png").unwrap(), image);
+    }
+
+    #[test]
+    fn missing_assets_are_named_in_the_error() {
+        let pack = AssetPack::from_bytes("test.zip", zip(&[("brick.png", b"", STORED)])).unwrap();
+        assert!(!pack.contains("stone.png"));
+        let err = format!("{:#}", pack.image("stone.png").unwrap_err());
+        assert!(
+            err.contains("\"stone.png\"") && err.contains("test.zip"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn sizes_the_archive_gets_wrong_are_errors() {
+        let zeros = vec![0; 100_000];
+        let archive = zip(&[("zeros", &zeros, DEFLATED)]);
+        // The uncompressed size is the last field before the name's length
+        let directory = archive.len() - 22 - 46 - "zeros".len();
+        for claimed in [10, 99_999, u32::MAX - 1] {
+            let mut archive = archive.clone();
+            archive[directory + 24..directory + 28].copy_from_slice(&claimed.to_le_bytes());
+            let pack = AssetPack::from_bytes("test.zip", archive).unwrap();
+            let err = format!("{:#}", pack.read("zeros").unwrap_err());
+            assert!(err.contains("corrupt"), "{claimed}: {err}");
+        }
+    }
+}
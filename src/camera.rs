@@ -0,0 +1,197 @@
+use anyhow::{bail, Context, Result};
+use cgmath::{Basis2, Deg, InnerSpace, Rad, Rotation, Rotation2, Vector2, VectorSpace};
+use serde::{Deserialize, Serialize};
+
+/// How far the horizon can move from the middle of the screen, as a fraction of its height.
+const MAX_PITCH: f32 = 0.5;
+/// How close the eye can get to the floor or the ceiling, in world units.
+const MIN_EYE_CLEARANCE: f32 = 0.05;
+
+/// The player's viewpoint. `facing_dir` should be unit length: ray distances are measured
+/// in multiples of it, and are only the perpendicular distance to the camera plane (which
+/// keeps walls free of fisheye) when it is.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera {
+    pub player_pos: Vector2<f32>,
+    pub facing_dir: Vector2<f32>,
+    pub view_plane: Vector2<f32>,
+    /// How far the view is tilted up, as the fraction of the screen height the horizon moves
+    /// down by. Negative looks down. The view is sheared rather than truly tilted, so walls
+    /// stay upright.
+    pub pitch: f32,
+    /// How high the eye is above the floor, in world units; the ceiling is 1 unit up.
+    /// 0.5 sees walls straight on, lower looks up at them.
+    pub eye_height: f32,
+}
+
+impl Camera {
+    /// A camera at `player_pos` looking along `facing_dir`, with a view plane `plane_length`
+    /// long on either side. The direction is normalized.
+    pub fn new(player_pos: Vector2<f32>, facing_dir: Vector2<f32>, plane_length: f32) -> Self {
+        let facing_dir = facing_dir.normalize();
+        Self {
+            player_pos,
+            facing_dir,
+            view_plane: Vector2::new(facing_dir.y, -facing_dir.x) * plane_length,
+            pitch: 0.,
+            eye_height: 0.5,
+        }
+    }
+
+    pub fn position(&self) -> Vector2<f32> {
+        self.player_pos
+    }
+
+    /// The facing direction as stored, which is unit length unless set by hand.
+    pub fn direction(&self) -> Vector2<f32> {
+        self.facing_dir
+    }
+
+    /// The view plane, from the center of the screen to its right edge.
+    pub fn plane(&self) -> Vector2<f32> {
+        self.view_plane
+    }
+
+    /// The unit vector the camera looks along.
+    pub fn forward(&self) -> Vector2<f32> {
+        self.facing_dir.normalize()
+    }
+
+    /// The unit vector to the camera's right, along the view plane.
+    pub fn right(&self) -> Vector2<f32> {
+        self.view_plane.normalize()
+    }
+
+    /// Moves the camera `distance` units along its facing direction; negative moves back.
+    pub fn move_forward(&mut self, distance: f32) {
+        self.player_pos += self.forward() * distance;
+    }
+
+    /// Moves the camera `distance` units to its right; negative moves left.
+    pub fn strafe(&mut self, distance: f32) {
+        self.player_pos += self.right() * distance;
+    }
+
+    /// The horizontal field of view implied by the plane and direction lengths.
+    pub fn fov(&self) -> Deg<f32> {
+        Rad(2. * (self.view_plane.magnitude() / self.facing_dir.magnitude()).atan()).into()
+    }
+
+    /// Rebuilds the view plane perpendicular to the facing direction for the given horizontal FOV.
+    pub fn set_fov(&mut self, fov: impl Into<Deg<f32>>) {
+        let half = Rad::from(fov.into()) / 2.;
+        self.facing_dir = self.facing_dir.normalize();
+        let right = Vector2::new(self.facing_dir.y, -self.facing_dir.x);
+        self.view_plane = right * half.0.tan();
+    }
+
+    /// Screen pixels per world unit at distance 1, matched to the horizontal scale of the view
+    /// plane so a unit of wall is as tall on screen as it is wide.
+    pub fn projection(&self, width: u32) -> f32 {
+        let half_fov_tan = self.view_plane.magnitude() / self.facing_dir.magnitude();
+        width as f32 / (2. * half_fov_tan)
+    }
+
+    /// Tilts the view up by `amount`, or down if negative, within the pitch limits.
+    pub fn look_up(&mut self, amount: f32) {
+        self.pitch = (self.pitch + amount).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Raises the eye by `amount`, or lowers it if negative, staying between floor and ceiling.
+    pub fn raise(&mut self, amount: f32) {
+        self.eye_height =
+            (self.eye_height + amount).clamp(MIN_EYE_CLEARANCE, 1. - MIN_EYE_CLEARANCE);
+    }
+
+    /// The screen row of the horizon, where the floor meets the ceiling at infinity.
+    pub fn horizon(&self, height: u32) -> f32 {
+        (0.5 + self.pitch) * height as f32
+    }
+
+    /// The camera `alpha` of the way from this one to `next`. The direction is blended and
+    /// renormalized, which for the small turns between two updates is as good as rotating
+    /// it. Field of view and pitch come from `next`, as they change outside of updates.
+    pub fn lerp(&self, next: &Camera, alpha: f32) -> Camera {
+        let mut camera = *next;
+        camera.player_pos = self.player_pos.lerp(next.player_pos, alpha);
+        camera.eye_height = self.eye_height + (next.eye_height - self.eye_height) * alpha;
+        let direction = self.forward().lerp(next.forward(), alpha);
+        // Directions half a turn apart blend to nothing, so there's no way between them
+        if direction.magnitude2() > f32::EPSILON {
+            camera.facing_dir = direction;
+            camera.set_fov(next.fov());
+        }
+        camera
+    }
+
+    /// Turns the camera, re-deriving the view plane from the facing direction so the
+    /// two can't drift out of perpendicular over many small rotations.
+    pub fn rotate(&mut self, angle: impl Into<Rad<f32>>) {
+        let fov = self.fov();
+        let rot: Basis2<f32> = Rotation2::from_angle(angle.into());
+        self.facing_dir = rot.rotate_vector(self.facing_dir);
+        self.set_fov(fov);
+    }
+}
+
+/// Reads scripted camera keyframes, one `x y facing_degrees` per line, with 0 degrees along
+/// +x and 90 along +y. Blank lines and lines starting with `#` are skipped. Each keyframe
+/// is a position and a unit facing direction.
+pub fn parse_path(source: &str) -> Result<Vec<(Vector2<f32>, Vector2<f32>)>> {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let numbers = line
+                .split_whitespace()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("invalid number in keyframe {line:?}"))?;
+            let [x, y, degrees] = numbers[..] else {
+                bail!("keyframe {line:?} should be x, y and a facing in degrees");
+            };
+            let (sin, cos) = Rad::from(Deg(degrees)).0.sin_cos();
+            Ok((Vector2::new(x, y), Vector2::new(cos, sin)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halving_the_fov_about_doubles_wall_height() {
+        let mut camera = Camera::new(Vector2::new(1.5, 1.5), Vector2::new(1., 0.), 1.);
+        for fov in [20., 60., 90.] {
+            camera.set_fov(Deg(fov));
+            let wide = camera.projection(640);
+            camera.set_fov(Deg(fov / 2.));
+            let narrow = camera.projection(640);
+            // A wall is projection / distance pixels tall, so it grows by the same ratio:
+            // a little over double, and closer to it the narrower the view
+            let ratio = narrow / wide;
+            let half_tan = |fov: f32| Rad::from(Deg(fov / 2.)).0.tan();
+            assert!((ratio - half_tan(fov) / half_tan(fov / 2.)).abs() < 1e-4);
+            assert!((2. ..2.5).contains(&ratio), "{fov}: {ratio}");
+        }
+        assert!((camera.projection(640) / camera.projection(320) - 2.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn moves_are_relative_to_the_facing_direction() {
+        // Facing +y, so the view plane, and the right, is +x
+        let mut camera = Camera::new(Vector2::new(2., 2.), Vector2::new(0., 3.), 0.66);
+        camera.move_forward(1.5);
+        assert_eq!(camera.position(), Vector2::new(2., 3.5));
+        camera.strafe(0.5);
+        assert_eq!(camera.position(), Vector2::new(2.5, 3.5));
+
+        camera.rotate(Deg(90.));
+        camera.move_forward(-1.);
+        camera.strafe(-2.);
+        let expected = Vector2::new(2.5, 3.5) + Vector2::new(1., 0.) + Vector2::new(0., -2.);
+        assert!((camera.position() - expected).magnitude() < 1e-5);
+    }
+}
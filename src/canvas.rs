@@ -0,0 +1,136 @@
+use crate::{font, texture::Texture};
+
+/// Clipped 2D drawing into a packed pixel buffer, used for overlays drawn over the 3D view.
+pub struct Canvas<'a> {
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(pixels: &'a mut [u32], width: usize, height: usize) -> Self {
+        debug_assert_eq!(pixels.len(), width * height);
+        Self {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixel at (x, y), or None outside the canvas.
+    pub fn pixel(&self, x: i32, y: i32) -> Option<u32> {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            Some(self.pixels[y as usize * self.width + x as usize])
+        } else {
+            None
+        }
+    }
+
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.pixels[y as usize * self.width + x as usize] = color;
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u32) {
+        let x0 = x.clamp(0, self.width as i32) as usize;
+        let x1 = (x + w).clamp(0, self.width as i32) as usize;
+        let y0 = y.clamp(0, self.height as i32) as usize;
+        let y1 = (y + h).clamp(0, self.height as i32) as usize;
+        for row in y0..y1 {
+            self.pixels[row * self.width + x0..row * self.width + x1].fill(color);
+        }
+    }
+
+    pub fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), color: u32) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.) as usize;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let (x, y) = (from.0 + dx * t, from.1 + dy * t);
+            self.put_pixel(x.floor() as i32, y.floor() as i32, color);
+        }
+    }
+
+    /// Draws a texture with its top-left corner at (x, y), each texel `scale` pixels square,
+    /// blended over what's there by its alpha. Anything off the canvas is clipped.
+    pub fn blit(&mut self, x: i32, y: i32, texture: &Texture, scale: usize) {
+        let scale = scale.max(1);
+        let clip = |start: i32, len: usize, size: usize| {
+            let end = (start + (len * scale) as i32).clamp(0, size as i32) as usize;
+            start.clamp(0, size as i32) as usize..end
+        };
+        for py in clip(y, texture.height(), self.height) {
+            let tex_y = (py as i32 - y) as usize / scale;
+            for px in clip(x, texture.width(), self.width) {
+                let texel = texture.texel((px as i32 - x) as usize / scale, tex_y);
+                blend_pixel(&mut self.pixels[py * self.width + px], texel);
+            }
+        }
+    }
+
+    /// Draws text with its top-left corner at (x, y), each font pixel `scale` pixels square.
+    /// Newlines start a new line; other characters outside the font leave a gap. Text running
+    /// off the canvas is clipped.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: u32, scale: usize) {
+        let scale = scale.max(1) as i32;
+        for (row, line) in text.lines().enumerate() {
+            let top = y + (row * font::LINE_HEIGHT) as i32 * scale;
+            if top >= self.height as i32 {
+                break;
+            }
+            for (column, c) in line.chars().enumerate() {
+                let left = x + (column * font::ADVANCE) as i32 * scale;
+                if left >= self.width as i32 {
+                    break;
+                }
+                let Some(glyph) = font::glyph(c) else {
+                    continue;
+                };
+                for gy in 0..font::GLYPH_HEIGHT {
+                    for gx in 0..font::GLYPH_WIDTH {
+                        if font::is_set(glyph, gx, gy) {
+                            let (px, py) = (left + gx as i32 * scale, top + gy as i32 * scale);
+                            self.fill_rect(px, py, scale, scale, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws `src` over `dst` by src's alpha, giving an opaque color.
+pub fn blend(dst: u32, src: u32) -> u32 {
+    let [sr, sg, sb, alpha] = src.to_le_bytes();
+    let [dr, dg, db, _] = dst.to_le_bytes();
+    let alpha = alpha as u32;
+    let mix = |s: u8, d: u8| ((s as u32 * alpha + d as u32 * (255 - alpha)) / 255) as u8;
+    u32::from_le_bytes([mix(sr, dr), mix(sg, dg), mix(sb, db), 0xFF])
+}
+
+/// `a` moved `t`/255 of the way to `b` in every channel, alpha included.
+pub fn mix(a: u32, b: u32, t: u8) -> u32 {
+    let (a, b, t) = (a.to_le_bytes(), b.to_le_bytes(), t as u32);
+    u32::from_le_bytes(std::array::from_fn(|i| {
+        ((a[i] as u32 * (255 - t) + b[i] as u32 * t) / 255) as u8
+    }))
+}
+
+/// Draws `src` over the pixel at `dst` by src's alpha, skipping the blend where src is fully
+/// transparent or fully opaque.
+pub fn blend_pixel(dst: &mut u32, src: u32) {
+    match src >> 24 {
+        0 => {}
+        0xFF => *dst = src,
+        _ => *dst = blend(*dst, src),
+    }
+}
@@ -0,0 +1,327 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::{camera::Camera, map::Map, renderer::Renderer};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Uniforms {
+    player_pos: [f32; 2],
+    facing_dir: [f32; 2],
+    view_plane: [f32; 2],
+    map_size: [u32; 2],
+    screen_size: [u32; 2],
+    projection: f32,
+    horizon: f32,
+    /// sRGB floor and ceiling colors, unpacked from the renderer's config.
+    floor_color: [f32; 4],
+    ceiling_color: [f32; 4],
+    eye_height: f32,
+    /// 1 if the map wraps at its edges, 0 if not.
+    wrap: u32,
+    /// The view distance in tiles.
+    max_dist: f32,
+    _padding: f32,
+}
+
+fn unpack_color(color: u32) -> [f32; 4] {
+    color.to_le_bytes().map(|channel| channel as f32 / 255.)
+}
+
+/// Runs the DDA in a compute shader, writing straight into a storage texture that the
+/// blit pipeline samples in place of the CPU-filled screen texture.
+pub struct GpuRaycaster {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    uniforms: wgpu::Buffer,
+    tiles: wgpu::Buffer,
+    /// The `Map::revision` the tile buffer holds, so it's only uploaded when the map changes.
+    tiles_revision: u64,
+    /// Each tile id's flat color, as in the renderer's palette.
+    palette: wgpu::Buffer,
+    /// The colors the palette buffer holds, so it's only uploaded when they change. Starts
+    /// zeroed, like the buffer.
+    palette_colors: [u32; 256],
+    output: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    display_bind_group: wgpu::BindGroup,
+}
+
+impl GpuRaycaster {
+    pub fn new(
+        device: &wgpu::Device,
+        display_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        size: PhysicalSize<u32>,
+        map: &Map,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("raycast bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("raycast.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Raycast Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Raycast Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("raycast uniforms"),
+            size: std::mem::size_of::<Uniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let tiles = create_tiles(device, map);
+        let palette_colors = [0; 256];
+        let palette = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("raycast palette"),
+            size: std::mem::size_of_val(&palette_colors) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let output = create_output(device, size);
+        let bind_group = create_bind_group(device, &layout, &uniforms, &tiles, &palette, &output);
+        let display_bind_group =
+            create_display_bind_group(device, display_layout, sampler, &output);
+
+        Self {
+            pipeline,
+            layout,
+            uniforms,
+            tiles,
+            tiles_revision: map.revision(),
+            palette,
+            palette_colors,
+            output,
+            bind_group,
+            display_bind_group,
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        display_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        size: PhysicalSize<u32>,
+    ) {
+        self.output = create_output(device, size);
+        self.bind_group = create_bind_group(
+            device,
+            &self.layout,
+            &self.uniforms,
+            &self.tiles,
+            &self.palette,
+            &self.output,
+        );
+        self.display_bind_group =
+            create_display_bind_group(device, display_layout, sampler, &self.output);
+    }
+
+    /// The bind group to draw the raycast output with, laid out like the CPU screen texture's.
+    pub fn display_bind_group(&self) -> &wgpu::BindGroup {
+        &self.display_bind_group
+    }
+
+    /// Uploads the camera and the renderer's colors, and the map and palette if they changed,
+    /// and records the compute pass into the encoder.
+    pub fn dispatch(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        map: &Map,
+        renderer: &Renderer,
+    ) {
+        if self.tiles_revision != map.revision() {
+            let tile_bytes = (map.tiles().len() * std::mem::size_of::<u32>()) as u64;
+            if self.tiles.size() != tile_bytes {
+                self.tiles = create_tiles(device, map);
+                self.bind_group = create_bind_group(
+                    device,
+                    &self.layout,
+                    &self.uniforms,
+                    &self.tiles,
+                    &self.palette,
+                    &self.output,
+                );
+            } else {
+                let tiles: Vec<u32> = map.tiles().iter().map(|&tile| tile as u32).collect();
+                queue.write_buffer(&self.tiles, 0, bytemuck::cast_slice(&tiles));
+            }
+            self.tiles_revision = map.revision();
+        }
+
+        let palette_colors = renderer.palette().table();
+        if self.palette_colors != palette_colors {
+            queue.write_buffer(&self.palette, 0, bytemuck::cast_slice(&palette_colors));
+            self.palette_colors = palette_colors;
+        }
+
+        let (width, height) = (self.output.width(), self.output.height());
+        let config = renderer.config();
+        let uniforms = Uniforms {
+            player_pos: camera.player_pos.into(),
+            facing_dir: camera.facing_dir.into(),
+            view_plane: camera.view_plane.into(),
+            map_size: [map.width() as u32, map.height() as u32],
+            screen_size: [width, height],
+            projection: camera.projection(width) / map.world_scale(),
+            horizon: camera.horizon(height),
+            floor_color: unpack_color(config.floor_color),
+            ceiling_color: unpack_color(config.ceiling_color),
+            eye_height: camera.eye_height,
+            wrap: map.wraps() as u32,
+            // Kept finite, as shaders needn't handle infinities
+            max_dist: (config.max_view_distance / map.world_scale()).min(f32::MAX),
+            _padding: 0.,
+        };
+        queue.write_buffer(&self.uniforms, 0, bytemuck::bytes_of(&uniforms));
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Raycast Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups(width.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
+
+fn create_tiles(device: &wgpu::Device, map: &Map) -> wgpu::Buffer {
+    let tiles: Vec<u32> = map.tiles().iter().map(|&tile| tile as u32).collect();
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("raycast tiles"),
+        contents: bytemuck::cast_slice(&tiles),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+fn create_output(device: &wgpu::Device, size: PhysicalSize<u32>) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("raycast output"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        // Storage textures can't be sRGB; the shader encodes for this itself
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniforms: &wgpu::Buffer,
+    tiles: &wgpu::Buffer,
+    palette: &wgpu::Buffer,
+    output: &wgpu::Texture,
+) -> wgpu::BindGroup {
+    let view = output.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("raycast bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: tiles.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: palette.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+fn create_display_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    output: &wgpu::Texture,
+) -> wgpu::BindGroup {
+    let view = output.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("raycast display bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
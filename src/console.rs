@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+
+use crate::{font, renderer::Renderer};
+
+/// Lines of output kept; older ones are dropped.
+const MAX_LINES: usize = 64;
+/// How many of the latest output lines show above the input line.
+const VISIBLE_LINES: usize = 8;
+const PROMPT: &str = "> ";
+/// Gap around the console's text, in pixels before scaling.
+const MARGIN: i32 = 4;
+const BACKGROUND_COLOR: u32 = 0xFF101010;
+const TEXT_COLOR: u32 = 0xFFE0E0E0;
+const ERROR_COLOR: u32 = 0xFF6060FF;
+const INPUT_COLOR: u32 = 0xFF80FFFF;
+
+/// A drop-down console across the top of the screen: a line being typed, and the output
+/// of what was typed before. It only collects and shows text; running the commands is up
+/// to whoever owns it.
+#[derive(Default)]
+pub struct Console {
+    open: bool,
+    input: String,
+    /// Output, oldest first, and whether each line is an error.
+    lines: VecDeque<(String, bool)>,
+}
+
+impl Console {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    /// Adds typed text to the input line. Characters the font can't draw are dropped.
+    pub fn type_text(&mut self, text: &str) {
+        self.input
+            .extend(text.chars().filter(|&c| font::glyph(c).is_some()));
+    }
+
+    /// Deletes the last character of the input line.
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    /// Clears the input line and returns what was on it, echoing it to the output. None if
+    /// it was blank.
+    pub fn submit(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.input);
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        self.print(format!("{PROMPT}{line}"));
+        Some(line.to_string())
+    }
+
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.push(line.into(), false);
+    }
+
+    /// Prints a line in the error color.
+    pub fn print_error(&mut self, line: impl Into<String>) {
+        self.push(line.into(), true);
+    }
+
+    fn push(&mut self, text: String, error: bool) {
+        // Each line of a multi-line message gets its own entry, so scrolling counts them
+        for line in text.lines() {
+            if self.lines.len() == MAX_LINES {
+                self.lines.pop_front();
+            }
+            self.lines.push_back((line.to_string(), error));
+        }
+    }
+
+    /// Draws the console over the top of the frame if it's open. Call after rendering.
+    pub fn draw(&self, renderer: &mut Renderer, scale: usize) {
+        if !self.open {
+            return;
+        }
+        let (margin, line_height) = (MARGIN * scale as i32, (font::LINE_HEIGHT * scale) as i32);
+        let width = renderer.size().width as i32;
+        let shown = self.lines.len().min(VISIBLE_LINES);
+        let height = 2 * margin + (VISIBLE_LINES + 1) as i32 * line_height;
+        renderer.fill_rect(0, 0, width, height, BACKGROUND_COLOR);
+
+        // Output fills upward from just above the input line
+        let mut y = margin + (VISIBLE_LINES - shown) as i32 * line_height;
+        for (line, error) in self.lines.iter().skip(self.lines.len() - shown) {
+            let color = if *error { ERROR_COLOR } else { TEXT_COLOR };
+            renderer.draw_text(margin, y, line, color, scale);
+            y += line_height;
+        }
+        // A line too long to fit scrolls to keep its end, where the typing is, in view
+        let input = format!("{PROMPT}{}_", self.input);
+        let fits = ((width - 2 * margin) / (font::ADVANCE * scale) as i32).max(1) as usize;
+        let skip = input.chars().count().saturating_sub(fits);
+        let input: String = input.chars().skip(skip).collect();
+        renderer.draw_text(margin, y, &input, INPUT_COLOR, scale);
+    }
+}
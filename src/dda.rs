@@ -0,0 +1,393 @@
+use cgmath::{Vector2, Zero};
+
+use crate::map::{Diagonal, Face, Map};
+
+/// How many cells a ray crosses on a wrapping map before giving up and seeing nothing. Rays
+/// there never leave the map, and one along an open row would otherwise go round forever.
+/// Walls this far off are fogged almost to nothing anyway.
+pub const WRAP_MAX_STEPS: usize = 256;
+/// `RayHit::side` for the face of a diagonal wall.
+pub const DIAGONAL_SIDE: u8 = 2;
+
+/// Walks a ray through the map grid with a DDA, yielding every cell it enters in order.
+/// Distances are in multiples of the direction, so with a unit direction they're world
+/// units. It ends if the ray steps below zero on either axis; the caller stops it at the
+/// far edges, where it knows the map's size.
+pub struct Dda {
+    cell: (usize, usize),
+    step: (isize, isize),
+    delta_dist: Vector2<f32>,
+    side_dist: Vector2<f32>,
+    /// The grid size to wrap cells around, and how many more steps to take, when wrapping.
+    wrap: Option<((usize, usize), usize)>,
+}
+
+/// A cell a ray entered.
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    pub x: usize,
+    pub y: usize,
+    /// 0 if the ray entered across a grid line of constant x, 1 for one of constant y.
+    pub side: u8,
+    /// How far along the ray it entered the cell.
+    pub dist: f32,
+    /// How far along the ray the next x and y grid lines are. The nearer is where it
+    /// leaves the cell.
+    pub next: Vector2<f32>,
+}
+
+impl Dda {
+    /// Starts in the cell holding `origin`, which must not be negative, heading along `dir`.
+    pub fn new(origin: Vector2<f32>, dir: Vector2<f32>) -> Self {
+        let cell = (origin.x as usize, origin.y as usize);
+        let delta_dist = Vector2::new(dir.x.recip().abs(), dir.y.recip().abs());
+        // The first grid line a ray crosses is its cell's near edge when it points the
+        // negative way along an axis, and the far edge otherwise. Scaling by delta_dist puts
+        // this in the same units as the steps, distance along the ray
+        let side_dist = Vector2::new(
+            if dir.x < 0. {
+                (origin.x - cell.0 as f32) * delta_dist.x
+            } else {
+                (cell.0 as f32 + 1. - origin.x) * delta_dist.x
+            },
+            if dir.y < 0. {
+                (origin.y - cell.1 as f32) * delta_dist.y
+            } else {
+                (cell.1 as f32 + 1. - origin.y) * delta_dist.y
+            },
+        );
+        Self {
+            cell,
+            step: (dir.x.signum() as isize, dir.y.signum() as isize),
+            delta_dist,
+            side_dist,
+            wrap: None,
+        }
+    }
+
+    /// Makes the ray wrap around a `width` by `height` grid instead of leaving it, stepping
+    /// off one edge onto the opposite one, and end after WRAP_MAX_STEPS cells. Distances
+    /// keep counting along the unwrapped ray. The origin must lie on the grid.
+    pub fn wrapping(mut self, width: usize, height: usize) -> Self {
+        self.wrap = Some(((width, height), WRAP_MAX_STEPS));
+        self
+    }
+
+    /// How far along the ray it takes to cross one whole cell along x and along y.
+    pub fn delta_dist(&self) -> Vector2<f32> {
+        self.delta_dist
+    }
+}
+
+impl Iterator for Dda {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        let size = match &mut self.wrap {
+            Some((_, 0)) => return None,
+            Some((size, remaining)) => {
+                *remaining -= 1;
+                Some(*size)
+            }
+            None => None,
+        };
+        let advance = |cell: usize, step: isize, size: Option<usize>| match size {
+            Some(size) => Some((cell as isize + step).rem_euclid(size as isize) as usize),
+            None => cell.checked_add_signed(step),
+        };
+        let (side, dist) = if self.side_dist.x < self.side_dist.y {
+            self.side_dist.x += self.delta_dist.x;
+            self.cell.0 = advance(self.cell.0, self.step.0, size.map(|size| size.0))?;
+            (0, self.side_dist.x - self.delta_dist.x)
+        } else {
+            self.side_dist.y += self.delta_dist.y;
+            self.cell.1 = advance(self.cell.1, self.step.1, size.map(|size| size.1))?;
+            (1, self.side_dist.y - self.delta_dist.y)
+        };
+        Some(Step {
+            x: self.cell.0,
+            y: self.cell.1,
+            side,
+            dist,
+            next: self.side_dist,
+        })
+    }
+}
+
+/// Where a ray struck a wall.
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    /// The tile id hit, or 0 if the ray left the map.
+    pub material: u8,
+    /// 0 if the face hit lies along a grid line of constant x, 1 for one of constant y, and
+    /// DIAGONAL_SIDE for a diagonal wall.
+    pub side: u8,
+    /// The world point hit.
+    pub point: Vector2<f32>,
+    /// How far along the ray the hit is, in multiples of its direction.
+    pub dist: f32,
+    /// Where along the face the ray landed, in [0, 1).
+    pub wall_x: f32,
+    /// Which side of its cell the face is on. None for diagonal faces, and misses.
+    pub face: Option<Face>,
+    /// How many cells the ray had entered by the time it got here.
+    pub steps: usize,
+}
+
+impl Default for RayHit {
+    fn default() -> Self {
+        RayHit {
+            material: 0,
+            side: 0,
+            point: Vector2::zero(),
+            dist: 0.,
+            wall_x: 0.,
+            face: None,
+            steps: 0,
+        }
+    }
+}
+
+/// Casts a ray from `pos` along `ray` and returns the opaque wall it stops at, or a hit on
+/// tile 0 if it leaves the map or goes further than `max_dist` without finding one. On a
+/// wrapping map the ray carries on across the edges, and hit points are brought back onto
+/// the map. The faces of see-through tiles it passes on the way are pushed onto
+/// `see_through`, nearest first. Distances are in multiples of `ray`, so for a column's ray
+/// (the facing direction plus part of the view plane) they're perpendicular to the camera
+/// plane.
+pub fn cast_ray(
+    map: &Map,
+    pos: Vector2<f32>,
+    ray: Vector2<f32>,
+    max_dist: f32,
+    see_through: &mut Vec<RayHit>,
+) -> RayHit {
+    let mut dda = map.dda(pos, ray).peekable();
+    let mut previous = 0;
+    let mut steps = 0;
+
+    // Standing in the open half of a diagonal wall's cell, the ray may meet the diagonal
+    // before it leaves
+    let cell = (pos.x as usize, pos.y as usize);
+    if let Some(diagonal) = map.diagonal(cell.0, cell.1) {
+        let exit = dda.peek().map_or(f32::INFINITY, |step| step.dist);
+        let local = pos - Vector2::new(cell.0 as f32, cell.1 as f32);
+        if let Some(dist) = diagonal
+            .intersect(local, ray)
+            .filter(|&dist| (0. ..=exit).contains(&dist))
+        {
+            let material = map.get(cell.0, cell.1).unwrap_or(0);
+            let point = map.wrap_position(pos + ray * dist);
+            return diagonal_hit(material, diagonal, point, dist, local + ray * dist, 0);
+        }
+    }
+
+    for step in dda {
+        steps += 1;
+        if step.dist > max_dist {
+            break;
+        }
+        // A ray that steps off the edge of the map hits nothing, leaving its column to the
+        // floor and ceiling passes and its depth at infinity
+        let Some(material) = map.get(step.x, step.y) else {
+            break;
+        };
+
+        if let Some(open) = map.thin_wall(step.x, step.y) {
+            // The wall's plane runs through the middle of the cell the way the map says,
+            // whichever side the ray came in by. It's hit if the ray reaches it before
+            // leaving the cell, and only where a door hasn't slid out of the way. Worked
+            // from the middle of the ray's path through the cell, like diagonals below
+            let side = map.thin_wall_side(step.x, step.y);
+            let exit = step.next.x.min(step.next.y);
+            let mid = (step.dist + exit) / 2.;
+            let mid_point = pos + ray * mid;
+            let (across, speed) = match side {
+                0 => (mid_point.x - mid_point.x.floor(), ray.x),
+                _ => (mid_point.y - mid_point.y.floor(), ray.y),
+            };
+            let dist = mid + (0.5 - across) / speed;
+            if (step.dist..=exit).contains(&dist) {
+                let point = map.wrap_position(pos + ray * dist);
+                let along = match side {
+                    0 => point.y,
+                    _ => point.x,
+                };
+                let wall_x = along - along.floor();
+                if wall_x >= open {
+                    return RayHit {
+                        material,
+                        side,
+                        point,
+                        dist,
+                        wall_x: wall_x - open,
+                        face: Face::hit(side, ray),
+                        steps,
+                    };
+                }
+            }
+            continue;
+        }
+
+        if let Some(diagonal) = map.diagonal(step.x, step.y) {
+            // Found from the middle of the ray's path through the cell, which is safely
+            // inside it, so wrapping and rounding at the edges don't matter
+            let exit = step.next.x.min(step.next.y);
+            let mid = (step.dist + exit) / 2.;
+            let mid_point = pos + ray * mid;
+            let local = mid_point - Vector2::new(mid_point.x.floor(), mid_point.y.floor());
+            // A ray coming in through an outer edge of the filled half hits it like any
+            // other wall face, and one coming into the open half can only meet the diagonal
+            if !diagonal.is_solid(local + ray * (step.dist - mid)) {
+                let hit = diagonal
+                    .intersect(local, ray)
+                    .map(|dist| mid + dist)
+                    .filter(|&dist| (step.dist..=exit).contains(&dist));
+                if let Some(dist) = hit {
+                    let point = map.wrap_position(pos + ray * dist);
+                    let local = local + ray * (dist - mid);
+                    return diagonal_hit(material, diagonal, point, dist, local, steps);
+                }
+                continue;
+            }
+        }
+
+        if material != 0 {
+            let point = map.wrap_position(pos + ray * step.dist);
+            let hit = RayHit {
+                material,
+                side: step.side,
+                point,
+                dist: step.dist,
+                wall_x: wall_x(point, ray, step.side),
+                face: Face::hit(step.side, ray),
+                steps,
+            };
+            if map.is_opaque(material) {
+                return hit;
+            }
+            // Keep going through see-through tiles, recording only the face where the ray
+            // enters a run of them so a long window is one pane, not one per cell
+            if material != previous {
+                see_through.push(hit);
+            }
+        }
+        previous = material;
+    }
+
+    RayHit {
+        steps,
+        ..RayHit::default()
+    }
+}
+
+/// A hit on the face of a diagonal wall of tile `material`, `dist` along the ray and at
+/// `local` in its cell.
+fn diagonal_hit(
+    material: u8,
+    diagonal: Diagonal,
+    point: Vector2<f32>,
+    dist: f32,
+    local: Vector2<f32>,
+    steps: usize,
+) -> RayHit {
+    RayHit {
+        material,
+        side: DIAGONAL_SIDE,
+        point,
+        dist,
+        wall_x: diagonal.wall_x(local),
+        face: None,
+        steps,
+    }
+}
+
+/// Where along the struck face the ray landed, in [0, 1).
+///
+/// Faces seen from the positive x and negative y directions are flipped so
+/// textures read the same way round on opposite walls.
+pub fn wall_x(point: Vector2<f32>, ray: Vector2<f32>, side: u8) -> f32 {
+    let (along, flip) = match side {
+        0 => (point.y, ray.x > 0.),
+        _ => (point.x, ray.y < 0.),
+    };
+    let wall_x = along - along.floor();
+    if flip {
+        (1. - wall_x).min(1. - f32::EPSILON)
+    } else {
+        wall_x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A door at (3, 3) with a wall above it and open floor on its other three sides.
+    const DOOR: &str = "1111111\n1000001\n1001001\n1004001\n1000001\n1000001\n1111111";
+
+    fn cast(map: &Map, pos: [f32; 2], ray: [f32; 2]) -> RayHit {
+        cast_ray(map, pos.into(), ray.into(), f32::INFINITY, &mut Vec::new())
+    }
+
+    #[test]
+    fn doors_run_the_same_way_from_every_side() {
+        let map = Map::parse(DOOR).unwrap();
+        assert_eq!(map.thin_wall_side(3, 3), 0);
+        // Entering the door's cell across x = 3, and from below across y = 4. Both meet it
+        // on x = 3.5, the line it runs along from the wall above
+        for (pos, ray, dist) in [
+            ([1.5, 3.5], [1., 0.2], 2.),
+            ([2.5, 5.5], [0.6, -1.], 5. / 3.),
+        ] {
+            let hit = cast(&map, pos, ray);
+            assert_eq!(hit.material, crate::map::DOOR_TILE);
+            assert_eq!(hit.side, 0);
+            assert!((hit.dist - dist).abs() < 1e-4, "{pos:?}: {}", hit.dist);
+            assert!((hit.point.x - 3.5).abs() < 1e-4, "{pos:?}: {:?}", hit.point);
+        }
+    }
+
+    #[test]
+    fn rays_stop_at_walls_and_pass_through_windows() {
+        // A window at (3, 1) in a corridor running along x
+        let map = Map::parse("1111111\n1009001\n1111111").unwrap();
+        let mut see_through = Vec::new();
+        let hit = cast_ray(
+            &map,
+            [1.5, 1.5].into(),
+            [1., 0.].into(),
+            10.,
+            &mut see_through,
+        );
+        assert_eq!((hit.material, hit.side, hit.face), (1, 0, Some(Face::West)));
+        assert_eq!((hit.dist, hit.point), (4.5, [6., 1.5].into()));
+        assert_eq!((hit.wall_x, hit.steps), (0.5, 5));
+        assert_eq!(see_through.len(), 1);
+        assert_eq!((see_through[0].material, see_through[0].dist), (9, 1.5));
+
+        // Cut off short of the wall, it sees only the window
+        see_through.clear();
+        let hit = cast_ray(
+            &map,
+            [1.5, 1.5].into(),
+            [1., 0.].into(),
+            3.,
+            &mut see_through,
+        );
+        assert_eq!((hit.material, see_through.len()), (0, 1));
+
+        // Distances are in multiples of the ray, which needn't be a unit vector
+        let hit = cast_ray(
+            &map,
+            [1.5, 1.5].into(),
+            [-2., 0.].into(),
+            10.,
+            &mut Vec::new(),
+        );
+        assert_eq!(
+            (hit.material, hit.dist, hit.face),
+            (1, 0.25, Some(Face::East))
+        );
+    }
+}
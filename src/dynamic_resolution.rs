@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+/// How much of each new frame's time goes into the smoothed average. Lower reacts slower
+/// but ignores more one-off spikes.
+const SMOOTHING: f32 = 0.1;
+/// Scales are rounded to multiples of this, so a frame time hovering near the edge of the
+/// budget doesn't reallocate the screen for a slightly different size every time.
+const SCALE_STEP: f32 = 1. / 32.;
+/// The scale is only raised while frames take less than this fraction of the budget, so
+/// there's a band of frame times where it's left alone instead of flipping up and down.
+const HEADROOM: f32 = 0.75;
+/// The most the scale changes by in one adjustment, as a factor either way.
+const MAX_CHANGE: f32 = 1.25;
+/// Frames to wait after changing the scale before judging it, for the average to catch up.
+const SETTLE_FRAMES: u32 = 20;
+
+/// Picks a render scale that keeps frames within a time budget: lowering it when rendering
+/// takes longer than the budget allows, and raising it back once there's room to spare.
+#[derive(Clone, Debug)]
+pub struct DynamicResolution {
+    /// The time a frame may take to render, in seconds.
+    budget: f32,
+    min_scale: f32,
+    max_scale: f32,
+    scale: f32,
+    /// The render time averaged over recent frames, in seconds. None until the first frame.
+    average: Option<f32>,
+    /// Frames left before the scale can change again.
+    settling: u32,
+}
+
+impl DynamicResolution {
+    /// Aims for `target_fps`, keeping the scale between `min_scale` and `max_scale`. Starts
+    /// at the largest scale.
+    pub fn new(target_fps: f32, min_scale: f32, max_scale: f32) -> Self {
+        Self {
+            budget: target_fps.recip(),
+            min_scale,
+            max_scale,
+            scale: max_scale,
+            average: None,
+            settling: 0,
+        }
+    }
+
+    pub fn target_fps(&self) -> f32 {
+        self.budget.recip()
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Starts from `scale` rather than the largest, such as the scale already in use.
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale.clamp(self.min_scale, self.max_scale);
+        self
+    }
+
+    /// Feeds in how long the last frame took to render. Returns the new scale when it
+    /// changes.
+    pub fn update(&mut self, render_time: Duration) -> Option<f32> {
+        let sample = render_time.as_secs_f32();
+        let average = match self.average {
+            Some(average) => average + (sample - average) * SMOOTHING,
+            None => sample,
+        };
+        self.average = Some(average);
+        if self.settling > 0 {
+            self.settling -= 1;
+            return None;
+        }
+        if average <= 0. || (average <= self.budget && average >= self.budget * HEADROOM) {
+            return None;
+        }
+
+        // Render time goes roughly with the pixel count, the square of the scale. Aim for
+        // the middle of the band where it's left alone
+        let aim = self.budget * (1. + HEADROOM) / 2.;
+        let factor = (aim / average).sqrt().clamp(MAX_CHANGE.recip(), MAX_CHANGE);
+        let scale = ((self.scale * factor / SCALE_STEP).round() * SCALE_STEP)
+            .clamp(self.min_scale, self.max_scale);
+        if scale == self.scale {
+            return None;
+        }
+        self.scale = scale;
+        self.settling = SETTLE_FRAMES;
+        Some(scale)
+    }
+}
@@ -0,0 +1,101 @@
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+const FRAC_BITS: u32 = 16;
+
+/// A signed fixed-point number with 16 fractional bits. Arithmetic on it is plain integer
+/// arithmetic, so it gives the same results on every machine, unlike floats whose rounding
+/// can differ between platforms and compiler settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << FRAC_BITS);
+    /// Stands in for the reciprocal of zero: far enough that it's never the nearer step, and
+    /// small enough that adding to it can't overflow.
+    pub const HUGE: Self = Self(1 << 48);
+
+    /// The nearest fixed-point value to `value`.
+    pub fn from_f32(value: f32) -> Self {
+        Self((value * Self::ONE.0 as f32).round() as i64)
+    }
+
+    pub fn from_int(value: i64) -> Self {
+        Self(value << FRAC_BITS)
+    }
+
+    /// `numerator / denominator`, rounded toward zero.
+    pub fn ratio(numerator: i64, denominator: i64) -> Self {
+        Self::from_int(numerator).div(denominator)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Self::ONE.0 as f32
+    }
+
+    /// The largest integer no greater than this.
+    pub fn floor(self) -> i64 {
+        self.0 >> FRAC_BITS
+    }
+
+    /// The part above the floor, in [0, 1).
+    pub fn fract(self) -> Self {
+        Self(self.0 & (Self::ONE.0 - 1))
+    }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// `1 / self`, or HUGE for zero.
+    pub fn recip(self) -> Self {
+        if self.0 == 0 {
+            return Self::HUGE;
+        }
+        Self((Self::ONE.0 * Self::ONE.0 / self.0).clamp(-Self::HUGE.0, Self::HUGE.0))
+    }
+
+    /// Halves the value, rounding toward negative infinity.
+    pub fn half(self) -> Self {
+        Self(self.0 >> 1)
+    }
+
+    fn div(self, divisor: i64) -> Self {
+        Self(self.0 / divisor)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        // Widened so the product of two large values can't overflow before it's shifted back
+        Self(((self.0 as i128 * other.0 as i128) >> FRAC_BITS) as i64)
+    }
+}
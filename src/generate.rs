@@ -0,0 +1,60 @@
+use anyhow::{bail, Result};
+use cgmath::Vector2;
+
+use crate::map::Map;
+
+/// The wall tiles scattered through a generated map: brick, stone and wood.
+const WALL_TILES: [u8; 3] = [1, 2, 3];
+
+/// SplitMix64, which is plenty for level layouts and gives the same maps on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    fn next_f32(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1 << 24) as f32
+    }
+}
+
+/// A random map walled in along its edges, with each interior tile a wall with probability
+/// `wall_density`. The player spawns in the middle tile, which is kept clear along with its
+/// eight neighbours. The same seed always gives the same map.
+pub fn generate(width: usize, height: usize, wall_density: f32, seed: u64) -> Result<Map> {
+    if width < 5 || height < 5 {
+        bail!("a generated map must be at least 5x5, not {width}x{height}");
+    }
+    if !(0. ..=1.).contains(&wall_density) {
+        bail!("wall density must be between 0 and 1, not {wall_density}");
+    }
+    let mut rng = Rng(seed);
+    let (spawn_x, spawn_y) = (width / 2, height / 2);
+    let mut tiles = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let border = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+            // Drawn for every tile so the layout doesn't depend on where the spawn is
+            let wall = rng.next_f32() < wall_density;
+            let tile = rng.next() as usize % WALL_TILES.len();
+            let near_spawn = x.abs_diff(spawn_x) <= 1 && y.abs_diff(spawn_y) <= 1;
+            tiles.push(if border || (wall && !near_spawn) {
+                WALL_TILES[tile]
+            } else {
+                0
+            });
+        }
+    }
+    let mut map = Map::new(width, height, tiles)?;
+    map.set_spawn(
+        Vector2::new(spawn_x as f32 + 0.5, spawn_y as f32 + 0.5),
+        Vector2::new(-1., 0.),
+    )?;
+    Ok(map)
+}
@@ -1,51 +1,130 @@
 use std::{cell::RefCell, rc::Rc, time::Instant};
 
-use anyhow::{Context, Result};
-use wgpu::{
-    Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, PowerPreference, RequestAdapterOptions,
-    TextureDescriptor, TextureFormat, TextureUsages, TextureViewDescriptor,
+use anyhow::{bail, Context, Result};
+use wgpu::{PowerPreference, RequestAdapterOptions, TextureViewDescriptor};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::Window,
 };
-use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::{renderer::Renderer, Camera};
+use crate::{
+    camera::Camera, compute::GpuRaycaster, console::Console, dynamic_resolution::DynamicResolution,
+    font, map::Map, renderer::Renderer, screen::Screen, timer::FrameTimer,
+};
+
+/// How the rendered frame is fitted to the window.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ScalingMode {
+    /// Rendered at the window's aspect ratio and stretched over all of it.
+    #[default]
+    Stretch,
+    /// Rendered at a fixed aspect ratio, width over height, as large as fits in the window
+    /// and centered, with black bars filling the margins.
+    Letterbox(f32),
+}
+
+impl ScalingMode {
+    /// The part of a `window`-sized surface the frame is drawn into.
+    pub fn fit(self, window: PhysicalSize<u32>) -> (PhysicalPosition<u32>, PhysicalSize<u32>) {
+        let aspect = match self {
+            ScalingMode::Letterbox(aspect) if aspect > 0. => aspect,
+            _ => return (PhysicalPosition::new(0, 0), window),
+        };
+        let (width, height) = (window.width as f32, window.height as f32);
+        // Wider windows get bars at the sides and taller ones at the top and bottom
+        let size = if width / height > aspect {
+            PhysicalSize::new((height * aspect).round() as u32, window.height)
+        } else {
+            PhysicalSize::new(window.width, (width / aspect).round() as u32)
+        };
+        let size = PhysicalSize::new(size.width.max(1), size.height.max(1));
+        let origin = PhysicalPosition::new(
+            (window.width - size.width) / 2,
+            (window.height - size.height) / 2,
+        );
+        (origin, size)
+    }
+}
 
 pub struct Graphics<'a> {
     surface: wgpu::Surface<'a>,
-    screen: wgpu::Texture,
+    screen: Screen,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
     render_pipeline: wgpu::RenderPipeline,
-    bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
 
+    map: Rc<RefCell<Map>>,
     renderer: Renderer,
-    last_frame: std::time::Instant,
+    gpu_raycaster: GpuRaycaster,
+    /// Raycast in a compute shader instead of on the CPU. The CPU renderer is the fallback
+    /// and the only path with textures and sprites.
+    gpu_raycast: bool,
+    render_scale: f32,
+    scaling_mode: ScalingMode,
+    /// Adjusts render_scale each frame to hold a frame rate, when enabled.
+    dynamic_resolution: Option<DynamicResolution>,
+    /// The supersampled frame averaged down to the screen size, reused between frames.
+    downsampled: Vec<u8>,
+    timer: FrameTimer,
+    console: Console,
 }
 
-impl<'a> Graphics<'a> {
-    pub async fn new(
-        camera: Rc<RefCell<Camera>>,
-        window: &'a Window,
-        size: PhysicalSize<u32>,
-    ) -> Result<Self> {
+const MIN_RENDER_SCALE: f32 = 0.25;
+/// Gap between the FPS counter and the top-right corner of the screen, in pixels.
+const FPS_MARGIN: i32 = 8;
+const FPS_COLOR: u32 = 0xFFFFFFFF;
+/// Where adapters are looked for, in order: a hardware one on the primary backends, then
+/// a software fallback on them, then anything on any backend, for VMs and headless CI.
+const ADAPTER_ATTEMPTS: [(wgpu::Backends, bool, &str); 3] = [
+    (wgpu::Backends::PRIMARY, false, "primary backends"),
+    (wgpu::Backends::PRIMARY, true, "fallback adapter"),
+    (wgpu::Backends::all(), false, "any backend"),
+];
+
+/// A surface for `window`, and an adapter that can draw to it, from the first of
+/// ADAPTER_ATTEMPTS that has one.
+async fn request_adapter(window: &Window) -> Result<(wgpu::Surface<'_>, wgpu::Adapter)> {
+    for (backends, force_fallback_adapter, what) in ADAPTER_ATTEMPTS {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
+            backends,
             ..Default::default()
         });
-
-        let surface = instance
-            .create_surface(window)
-            .context("failed to create surface")?;
-
+        let surface = match instance.create_surface(window) {
+            Ok(surface) => surface,
+            Err(err) => {
+                log::warn!("failed to create surface on {what}: {err}");
+                continue;
+            }
+        };
         let adapter_options = RequestAdapterOptions {
             power_preference: PowerPreference::default(),
             compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
+            force_fallback_adapter,
         };
-        let adapter = instance
-            .request_adapter(&adapter_options)
-            .await
-            .context("failed to request adapter")?;
+        match instance.request_adapter(&adapter_options).await {
+            Some(adapter) => {
+                let info = adapter.get_info();
+                log::info!("using {} on {:?}, found on {what}", info.name, info.backend);
+                return Ok((surface, adapter));
+            }
+            None => log::warn!("no adapter on {what}"),
+        }
+    }
+    bail!("failed to request adapter")
+}
+
+impl<'a> Graphics<'a> {
+    pub async fn new(
+        camera: Rc<RefCell<Camera>>,
+        map: Rc<RefCell<Map>>,
+        window: &'a Window,
+        size: PhysicalSize<u32>,
+    ) -> Result<Self> {
+        let (surface, adapter) = request_adapter(window).await?;
 
         let device_descriptor = wgpu::DeviceDescriptor {
             required_features: wgpu::Features::empty(),
@@ -76,22 +155,6 @@ impl<'a> Graphics<'a> {
             desired_maximum_frame_latency: 2,
         };
 
-        let screen_descriptor = TextureDescriptor {
-            label: Some("screen"),
-            size: Extent3d {
-                width: size.width,
-                height: size.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: TextureFormat::Rgba8UnormSrgb,
-            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        };
-        let screen = device.create_texture(&screen_descriptor);
-        let view = screen.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -115,20 +178,7 @@ impl<'a> Graphics<'a> {
                     },
                 ],
             });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind group"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
+        let screen = Screen::new(&device, &texture_bind_group_layout, &sampler, size);
 
         let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
         let render_pipeline_layout =
@@ -175,75 +225,250 @@ impl<'a> Graphics<'a> {
             cache: None,
         });
 
-        let renderer = Renderer::new(camera, size);
+        let gpu_raycaster = GpuRaycaster::new(
+            &device,
+            &texture_bind_group_layout,
+            &sampler,
+            size,
+            &map.borrow(),
+        );
+        let renderer =
+            Renderer::new(camera, map.clone(), size).context("failed to construct renderer")?;
         Ok(Self {
             surface,
             screen,
             device,
             queue,
             config,
-            bind_group,
+            present_modes: surface_caps.present_modes,
+            texture_bind_group_layout,
+            sampler,
             render_pipeline,
 
+            map,
             renderer,
-            last_frame: Instant::now(),
+            gpu_raycaster,
+            gpu_raycast: false,
+            render_scale: 1.,
+            scaling_mode: ScalingMode::default(),
+            dynamic_resolution: None,
+            downsampled: Vec::new(),
+            timer: FrameTimer::default(),
+            console: Console::default(),
         })
     }
 
+    /// Reconfigures the surface for a new window size. A zero-sized window, as some platforms
+    /// report while minimized, can't have a surface, so the old one is kept until it's restored.
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
         self.config.width = new_size.width;
         self.config.height = new_size.height;
         self.surface.configure(&self.device, &self.config);
+        self.resize_screen();
     }
 
-    fn queue(&self) {
-        let (width, height) = (self.config.width, self.config.height);
-        let texture = ImageCopyTexture {
-            texture: &self.screen,
-            mip_level: 0,
-            origin: Origin3d::ZERO,
-            aspect: wgpu::TextureAspect::All,
-        };
-        let size = Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-        let data_layout = ImageDataLayout {
-            offset: 0,
-            bytes_per_row: Some(width * 4),
-            rows_per_image: Some(height),
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    pub fn supports_present_mode(&self, mode: wgpu::PresentMode) -> bool {
+        self.present_modes.contains(&mode)
+    }
+
+    /// Reconfigures the surface with the given present mode, falling back to Fifo (vsync),
+    /// which every surface supports, if the mode isn't available. Returns the mode in use.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let mode = if self.supports_present_mode(mode) {
+            mode
+        } else {
+            log::warn!("present mode {mode:?} is not supported, falling back to Fifo");
+            wgpu::PresentMode::Fifo
         };
-        let pixels = self.renderer.pixels();
-        self.queue.write_texture(texture, pixels, data_layout, size);
+        self.config.present_mode = mode;
+        self.surface.configure(&self.device, &self.config);
+        mode
     }
 
-    pub fn render(&mut self) -> std::result::Result<(), wgpu::SurfaceError> {
-        self.renderer.render();
-        self.queue();
+    pub fn renderer(&self) -> &Renderer {
+        &self.renderer
+    }
 
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&TextureViewDescriptor::default());
+    pub fn renderer_mut(&mut self) -> &mut Renderer {
+        &mut self.renderer
+    }
+
+    /// Timing of the presented frames.
+    pub fn timer(&self) -> &FrameTimer {
+        &self.timer
+    }
+
+    pub fn timer_mut(&mut self) -> &mut FrameTimer {
+        &mut self.timer
+    }
+
+    /// The console drawn over the frame, for typing commands.
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    pub fn gpu_raycast(&self) -> bool {
+        self.gpu_raycast
+    }
+
+    pub fn set_gpu_raycast(&mut self, enabled: bool) {
+        self.gpu_raycast = enabled;
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Sets the internal render resolution as a fraction of the window size, clamped to 25%-100%.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale.clamp(MIN_RENDER_SCALE, 1.);
+        self.resize_screen();
+    }
+
+    pub fn scaling_mode(&self) -> ScalingMode {
+        self.scaling_mode
+    }
+
+    /// Changes how the frame fits the window, resizing the render resolution to match.
+    pub fn set_scaling_mode(&mut self, mode: ScalingMode) {
+        self.scaling_mode = mode;
+        self.resize_screen();
+    }
+
+    /// The surface's size.
+    fn window_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.config.width, self.config.height)
+    }
+
+    pub fn dynamic_resolution(&self) -> Option<&DynamicResolution> {
+        self.dynamic_resolution.as_ref()
+    }
+
+    /// Hands the render scale over to `controller`, or takes it back with None. Its scale is
+    /// applied straight away.
+    pub fn set_dynamic_resolution(&mut self, controller: Option<DynamicResolution>) {
+        if let Some(controller) = &controller {
+            self.set_render_scale(controller.scale());
+        }
+        self.dynamic_resolution = controller;
+    }
+
+    /// Reallocates the CPU pixel buffer and the screen texture it's uploaded to, sized to
+    /// the part of the window the frame fills.
+    fn resize_screen(&mut self) {
+        let (_, frame) = self.scaling_mode.fit(self.window_size());
+        let size = PhysicalSize::new(
+            ((frame.width as f32 * self.render_scale) as u32).max(1),
+            ((frame.height as f32 * self.render_scale) as u32).max(1),
+        );
+        if size == self.renderer.output_size() {
+            return;
+        }
+        self.renderer.resize(size);
+        self.screen = Screen::new(
+            &self.device,
+            &self.texture_bind_group_layout,
+            &self.sampler,
+            size,
+        );
+        self.gpu_raycaster.resize(
+            &self.device,
+            &self.texture_bind_group_layout,
+            &self.sampler,
+            size,
+        );
+    }
 
+    /// Uploads the rows of the frame changed since the last upload, if any, first
+    /// downsampling them to the screen size if the renderer is supersampling.
+    fn queue(&mut self) {
+        let Some(rows) = self.renderer.take_dirty_rows() else {
+            return;
+        };
+        let factor = self.renderer.supersample() as usize;
+        if factor == 1 {
+            let rows = rows.start as u32..rows.end as u32;
+            self.screen
+                .upload_rows(&self.queue, self.renderer.pixels(), rows);
+            return;
+        }
+        let size = self.renderer.output_size();
+        self.downsampled
+            .resize((size.width * size.height * 4) as usize, 0);
+        let rows = rows.start / factor..rows.end.div_ceil(factor);
+        self.renderer
+            .downsample(rows.clone(), &mut self.downsampled);
+        let rows = rows.start as u32..rows.end as u32;
+        self.screen
+            .upload_rows(&self.queue, &self.downsampled, rows);
+    }
+
+    /// Draws and presents a frame seen from `camera`, which needn't be the shared camera.
+    pub fn render(&mut self, camera: Camera) -> std::result::Result<(), wgpu::SurfaceError> {
+        let start = Instant::now();
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
+        self.renderer.clamp_camera();
+        let bind_group = if self.gpu_raycast {
+            let map = self.map.borrow();
+            let camera = Camera {
+                player_pos: map.clamp_position(camera.player_pos),
+                ..camera
+            };
+            self.gpu_raycaster.dispatch(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &camera,
+                &map,
+                &self.renderer,
+            );
+            self.gpu_raycaster.display_bind_group()
+        } else {
+            self.renderer.render_from(camera);
+            let fps = format!("FPS {:.0}", self.timer.fps());
+            let scale = self.renderer.supersample() as usize;
+            let margin = FPS_MARGIN * scale as i32;
+            let (text_width, _) = font::text_size(&fps, scale);
+            let x = self.renderer.size().width as i32 - text_width as i32 - margin;
+            self.renderer.draw_text(x, margin, &fps, FPS_COLOR, scale);
+            self.console.draw(&mut self.renderer, scale);
+            self.queue();
+            self.screen.bind_group()
+        };
+        // Timed before waiting for the surface, which can block on vsync. The GPU
+        // raycaster's work happens after this, so only the CPU renderer's cost is seen
+        let render_time = start.elapsed();
+
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.14,
-                        g: 0.0,
-                        b: 0.0,
-                        a: 1.0,
+                    load: wgpu::LoadOp::Clear(match self.scaling_mode {
+                        ScalingMode::Stretch => self.renderer.config().clear_color,
+                        ScalingMode::Letterbox(_) => wgpu::Color::BLACK,
                     }),
                     store: wgpu::StoreOp::Store,
                 },
@@ -253,18 +478,33 @@ impl<'a> Graphics<'a> {
             timestamp_writes: None,
         });
 
+        let (origin, frame) = self.scaling_mode.fit(self.window_size());
+        render_pass.set_viewport(
+            origin.x as f32,
+            origin.y as f32,
+            frame.width as f32,
+            frame.height as f32,
+            0.,
+            1.,
+        );
         render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(0, bind_group, &[]);
         render_pass.draw(0..6, 0..1);
 
         drop(render_pass);
 
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
+        self.timer.tick();
 
-        let duration = self.last_frame.elapsed();
-        println!("FPS: {}", 1000000000. / (duration.as_nanos() as f64));
-        self.last_frame = Instant::now();
+        if let Some(scale) = self
+            .dynamic_resolution
+            .as_mut()
+            .and_then(|controller| controller.update(render_time))
+        {
+            self.set_render_scale(scale);
+            log::debug!("render scale: {:.0}%", self.render_scale * 100.);
+        }
         Ok(())
     }
 }
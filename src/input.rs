@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, KeyCode, NamedKey, PhysicalKey},
+};
+
+use crate::keymap::{Action, Keymap};
+
+/// Keyboard state split into the two ways keys are consumed.
+///
+/// Game actions are bound to physical key positions so WASD sits in the same
+/// place on QWERTY, AZERTY or Dvorak. Text entry uses the logical key, which
+/// reflects the active layout, so typed characters come out as labelled.
+#[derive(Default)]
+pub struct Input {
+    held: HashSet<KeyCode>,
+    text: String,
+    /// Which keys trigger which actions.
+    keymap: Keymap,
+}
+
+impl Input {
+    pub fn new(keymap: Keymap) -> Self {
+        Self {
+            keymap,
+            ..Self::default()
+        }
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn keymap_mut(&mut self) -> &mut Keymap {
+        &mut self.keymap
+    }
+
+    pub fn handle_key(&mut self, event: &KeyEvent) {
+        self.handle_physical(event.physical_key, event.state);
+        self.handle_logical(&event.logical_key, event.text.as_deref(), event.state);
+    }
+
+    /// Tracks which key positions are held, which is what actions go by.
+    fn handle_physical(&mut self, key: PhysicalKey, state: ElementState) {
+        if let PhysicalKey::Code(code) = key {
+            match state {
+                ElementState::Pressed => self.held.insert(code),
+                ElementState::Released => self.held.remove(&code),
+            };
+        }
+    }
+
+    /// Collects typed text, which goes by the layout.
+    fn handle_logical(&mut self, key: &Key, text: Option<&str>, state: ElementState) {
+        if state == ElementState::Pressed {
+            match key {
+                Key::Named(NamedKey::Backspace) => {
+                    self.text.pop();
+                }
+                _ => {
+                    if let Some(text) = text {
+                        self.text.extend(text.chars().filter(|c| !c.is_control()));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_held(&self, key: KeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// Whether any key bound to `action` is held.
+    pub fn is_active(&self, action: Action) -> bool {
+        self.held
+            .iter()
+            .any(|&key| self.keymap.action(key) == Some(action))
+    }
+
+    /// The action `key` is bound to, if any.
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.keymap.action(key)
+    }
+
+    /// Drains the text typed since the last call.
+    pub fn take_text(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+
+    /// Forgets held keys, e.g. when focus is lost and release events won't arrive.
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Presses the key at `code` on a layout where it types `typed`.
+    fn press(input: &mut Input, code: KeyCode, typed: &str) {
+        input.handle_physical(PhysicalKey::Code(code), ElementState::Pressed);
+        input.handle_logical(
+            &Key::Character(typed.into()),
+            Some(typed),
+            ElementState::Pressed,
+        );
+    }
+
+    #[test]
+    fn actions_follow_physical_keys() {
+        // On AZERTY the key in QWERTY's W position types Z, and vice versa
+        let mut input = Input::default();
+        press(&mut input, KeyCode::KeyW, "z");
+        assert!(input.is_active(Action::MoveForward));
+        assert_eq!(input.take_text(), "z");
+
+        let mut input = Input::default();
+        press(&mut input, KeyCode::KeyZ, "w");
+        assert!(!input.is_active(Action::MoveForward));
+        assert_eq!(input.take_text(), "w");
+    }
+
+    #[test]
+    fn releasing_a_key_ends_its_action() {
+        let mut input = Input::default();
+        press(&mut input, KeyCode::KeyW, "w");
+        input.handle_physical(PhysicalKey::Code(KeyCode::KeyW), ElementState::Released);
+        assert!(!input.is_active(Action::MoveForward));
+    }
+}
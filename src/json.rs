@@ -0,0 +1,411 @@
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+
+/// How deeply arrays and objects may nest. The parser recurses once per level, so without a
+/// limit a document of nothing but brackets could overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON document. Objects keep their keys in file order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Parses a whole document, which must hold exactly one value.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            source,
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != source.len() {
+            bail!("trailing characters at {}", parser.location());
+        }
+        Ok(value)
+    }
+
+    /// The value under `key` if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(entries) => Some(entries),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the value as compact JSON, which parses back to the same value. Numbers JSON
+/// can't hold, infinities and NaN, are written as null.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(number) if number.is_finite() => write!(f, "{number}"),
+            Value::Number(_) => write!(f, "null"),
+            Value::String(string) => write_string(f, string),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Writes `string` quoted, escaping what JSON requires.
+fn write_string(f: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    /// How many arrays and objects the current position is inside.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    /// The line and column of the current position, for error messages.
+    fn location(&self) -> String {
+        let before = &self.source[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        format!("line {line} column {column}")
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            bail!("expected '{}' at {}", byte as char, self.location());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => {
+                for (word, value) in [
+                    ("null", Value::Null),
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false)),
+                ] {
+                    if self.source[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                bail!("expected a value at {}", self.location())
+            }
+        }
+    }
+
+    /// Parses a comma-separated sequence between `open` and `close`, calling `item` for each.
+    fn sequence(
+        &mut self,
+        open: u8,
+        close: u8,
+        mut item: impl FnMut(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        self.expect(open)?;
+        if self.depth == MAX_DEPTH {
+            bail!("nested deeper than {MAX_DEPTH} at {}", self.location());
+        }
+        self.depth += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            self.depth -= 1;
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(byte) if byte == close => {
+                    self.pos += 1;
+                    self.depth -= 1;
+                    return Ok(());
+                }
+                _ => bail!("expected ',' or '{}' at {}", close as char, self.location()),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        let mut entries = Vec::new();
+        self.sequence(b'{', b'}', |parser| {
+            parser.skip_whitespace();
+            let key = parser.string()?;
+            parser.expect(b':')?;
+            entries.push((key, parser.value()?));
+            Ok(())
+        })?;
+        Ok(Value::Object(entries))
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        let mut values = Vec::new();
+        self.sequence(b'[', b']', |parser| {
+            values.push(parser.value()?);
+            Ok(())
+        })?;
+        Ok(Value::Array(values))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if self.peek() != Some(b'"') {
+            bail!("expected a string at {}", self.location());
+        }
+        self.pos += 1;
+        let mut string = String::new();
+        loop {
+            let rest = &self.source[self.pos..];
+            let c = rest
+                .chars()
+                .next()
+                .with_context(|| format!("unterminated string at {}", self.location()))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = self.source[self.pos..]
+                        .chars()
+                        .next()
+                        .with_context(|| format!("unterminated string at {}", self.location()))?;
+                    self.pos += escape.len_utf8();
+                    string.push(match escape {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            // Surrogate pairs aren't combined, so only the basic plane decodes
+                            let code = self
+                                .source
+                                .get(self.pos..self.pos + 4)
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(char::from_u32)
+                                .with_context(|| {
+                                    format!("invalid unicode escape at {}", self.location())
+                                })?;
+                            self.pos += 4;
+                            code
+                        }
+                        _ => bail!("invalid escape at {}", self.location()),
+                    });
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.pos += 1;
+        }
+        let text = &self.source[start..self.pos];
+        let number = text
+            .parse()
+            .with_context(|| format!("invalid number {text:?} at {}", self.location()))?;
+        Ok(Value::Number(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_owned())
+    }
+
+    #[test]
+    fn strings_decode_their_escapes() {
+        let value = Value::parse(r#""quote \" slash \\ \/ \n\t\r\b\f é中""#).unwrap();
+        assert_eq!(value, string("quote \" slash \\ / \n\t\r\u{8}\u{c} é中"));
+        assert_eq!(Value::parse(r#""\u0041\u0062""#).unwrap(), string("Ab"));
+        for invalid in [
+            r#""\x""#,
+            r#""\u12""#,
+            r#""\uzzzz""#,
+            r#""\ud800""#,
+            r#""open"#,
+        ] {
+            assert!(Value::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn numbers_parse_as_doubles() {
+        for (source, number) in [
+            ("0", 0.),
+            ("-7", -7.),
+            ("3.25", 3.25),
+            ("1e3", 1000.),
+            ("-2.5E-2", -0.025),
+            ("1e+2", 100.),
+        ] {
+            assert_eq!(
+                Value::parse(source).unwrap(),
+                Value::Number(number),
+                "{source}"
+            );
+        }
+        for invalid in ["-", "1.2.3", "1e", "--1", "+1", ".5"] {
+            assert!(Value::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn trailing_commas_are_rejected() {
+        for invalid in ["[1, 2,]", r#"{"a": 1,}"#, "[,]", "[1,,2]"] {
+            assert!(Value::parse(invalid).is_err(), "{invalid}");
+        }
+        let value = Value::parse(r#"{ "a" : [ 1 , true , null ] , "b" : {} }"#).unwrap();
+        assert_eq!(
+            value,
+            Value::Object(vec![
+                (
+                    "a".to_owned(),
+                    Value::Array(vec![Value::Number(1.), Value::Bool(true), Value::Null])
+                ),
+                ("b".to_owned(), Value::Object(Vec::new())),
+            ])
+        );
+    }
+
+    #[test]
+    fn errors_say_where_they_are() {
+        let error = Value::parse("{\n  \"a\": [1, 2\n}")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "expected ',' or ']' at line 3 column 1");
+        let error = Value::parse("[1] 2").unwrap_err().to_string();
+        assert_eq!(error, "trailing characters at line 1 column 5");
+        for invalid in ["", "nul", "{1: 2}", "{\"a\" 1}", "[1"] {
+            assert!(Value::parse(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        let error = Value::parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert!(
+            error.to_string().starts_with("nested deeper than"),
+            "{error}"
+        );
+        // Far deeper than the stack would hold without the limit
+        assert!(Value::parse(&"[".repeat(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn courtyard_round_trips() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/maps/courtyard.json");
+        let value = Value::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert!(value.get("tiles").and_then(Value::as_array).is_some());
+        let written = value.to_string();
+        assert_eq!(Value::parse(&written).unwrap(), value);
+
+        let escaped = string("tab\t \"quoted\" back\\slash \u{1} é");
+        assert_eq!(Value::parse(&escaped.to_string()).unwrap(), escaped);
+    }
+}
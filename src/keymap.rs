@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use winit::keyboard::KeyCode;
+
+/// Something the player can bind a key to. The first group act for as long as their key is
+/// held; the rest fire once per press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    TurnLeft,
+    TurnRight,
+    Sprint,
+    RaiseEye,
+    LowerEye,
+
+    Use,
+    NextLevel,
+    Pause,
+    ToggleNoclip,
+    Quicksave,
+    Quickload,
+    Screenshot,
+    ToggleRecording,
+    /// Lets go of the cursor grabbed for mouse-look.
+    ReleaseCursor,
+    Quit,
+    DecreaseRenderScale,
+    IncreaseRenderScale,
+    ToggleDynamicResolution,
+    CycleScalingMode,
+    CyclePresentMode,
+    CycleFrameCap,
+    CycleRenderMode,
+    CycleFloorColor,
+    ToggleMinimap,
+    ToggleSky,
+    ToggleRays,
+    ToggleGpuRaycast,
+    ToggleSupersampling,
+    ToggleConsole,
+}
+
+/// The default bindings. Keys are physical positions, so WASD sits in the same place on
+/// any layout. Quitting is F10, as in DOOM, leaving Escape to free the cursor.
+const DEFAULT_BINDINGS: [(KeyCode, Action); 36] = [
+    (KeyCode::KeyW, Action::MoveForward),
+    (KeyCode::ArrowUp, Action::MoveForward),
+    (KeyCode::KeyS, Action::MoveBackward),
+    (KeyCode::ArrowDown, Action::MoveBackward),
+    (KeyCode::KeyA, Action::StrafeLeft),
+    (KeyCode::KeyD, Action::StrafeRight),
+    (KeyCode::ArrowLeft, Action::TurnLeft),
+    (KeyCode::ArrowRight, Action::TurnRight),
+    (KeyCode::ShiftLeft, Action::Sprint),
+    (KeyCode::ShiftRight, Action::Sprint),
+    (KeyCode::PageUp, Action::RaiseEye),
+    (KeyCode::PageDown, Action::LowerEye),
+    (KeyCode::KeyE, Action::Use),
+    (KeyCode::KeyN, Action::NextLevel),
+    (KeyCode::KeyP, Action::Pause),
+    (KeyCode::KeyF, Action::ToggleNoclip),
+    (KeyCode::F6, Action::Quicksave),
+    (KeyCode::F9, Action::Quickload),
+    (KeyCode::F12, Action::Screenshot),
+    (KeyCode::KeyR, Action::ToggleRecording),
+    (KeyCode::Escape, Action::ReleaseCursor),
+    (KeyCode::F10, Action::Quit),
+    (KeyCode::Minus, Action::DecreaseRenderScale),
+    (KeyCode::Equal, Action::IncreaseRenderScale),
+    (KeyCode::KeyB, Action::ToggleDynamicResolution),
+    (KeyCode::KeyZ, Action::CycleScalingMode),
+    (KeyCode::KeyV, Action::CyclePresentMode),
+    (KeyCode::KeyL, Action::CycleFrameCap),
+    (KeyCode::KeyT, Action::CycleRenderMode),
+    (KeyCode::KeyC, Action::CycleFloorColor),
+    (KeyCode::KeyM, Action::ToggleMinimap),
+    (KeyCode::KeyK, Action::ToggleSky),
+    (KeyCode::KeyY, Action::ToggleRays),
+    (KeyCode::KeyG, Action::ToggleGpuRaycast),
+    (KeyCode::KeyO, Action::ToggleSupersampling),
+    (KeyCode::Backquote, Action::ToggleConsole),
+];
+
+/// Which key does what. Each key triggers at most one action, and an action can have any
+/// number of keys.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: HashMap<KeyCode, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::from(DEFAULT_BINDINGS),
+        }
+    }
+}
+
+impl Keymap {
+    /// A keymap with nothing bound.
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Makes `key` trigger `action`, replacing whatever it did before. The action's other
+    /// keys still work; `unbind_action` first to move it to this key alone.
+    pub fn bind(&mut self, key: KeyCode, action: Action) {
+        self.bindings.insert(key, action);
+    }
+
+    /// Builder form of `bind`.
+    pub fn with(mut self, key: KeyCode, action: Action) -> Self {
+        self.bind(key, action);
+        self
+    }
+
+    /// Makes `key` do nothing.
+    pub fn unbind(&mut self, key: KeyCode) {
+        self.bindings.remove(&key);
+    }
+
+    /// Takes every key off `action`.
+    pub fn unbind_action(&mut self, action: Action) {
+        self.bindings.retain(|_, bound| *bound != action);
+    }
+
+    /// The action `key` triggers, if any.
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+
+    /// The keys bound to `action`, in no particular order.
+    pub fn keys(&self, action: Action) -> impl Iterator<Item = KeyCode> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, &bound)| bound == action)
+            .map(|(&key, _)| key)
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::map::Map;
+
+/// The maps the player can switch between, kept pristine so a level restarts fresh each
+/// time it's loaded.
+pub struct LevelSet {
+    maps: Vec<Map>,
+    current: usize,
+}
+
+impl Default for LevelSet {
+    /// The built-in map on its own.
+    fn default() -> Self {
+        Self {
+            maps: vec![Map::default()],
+            current: 0,
+        }
+    }
+}
+
+impl LevelSet {
+    pub fn new(maps: Vec<Map>) -> Result<Self> {
+        if maps.is_empty() {
+            bail!("a level set needs at least one level");
+        }
+        Ok(Self { maps, current: 0 })
+    }
+
+    /// Loads each map file as a level.
+    pub fn load(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Self> {
+        let maps = paths.into_iter().map(Map::load).collect::<Result<_>>()?;
+        Self::new(maps)
+    }
+
+    pub fn len(&self) -> usize {
+        self.maps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.is_empty()
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Makes `index` the current level. Returns false if there is no such level.
+    pub fn set_current(&mut self, index: usize) -> bool {
+        if index < self.maps.len() {
+            self.current = index;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Adds a level after the others and returns its index.
+    pub fn push(&mut self, map: Map) -> usize {
+        self.maps.push(map);
+        self.maps.len() - 1
+    }
+
+    pub fn current_map(&self) -> &Map {
+        &self.maps[self.current]
+    }
+}
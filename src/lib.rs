@@ -0,0 +1,23 @@
+pub mod asset_pack;
+pub mod camera;
+pub mod canvas;
+pub mod compute;
+pub mod console;
+pub mod dda;
+pub mod dynamic_resolution;
+pub mod fixed;
+pub mod font;
+pub mod generate;
+pub mod graphics;
+pub mod input;
+pub mod json;
+pub mod keymap;
+pub mod level;
+pub mod lighting;
+pub mod map;
+pub mod palette;
+pub mod renderer;
+pub mod save;
+pub mod screen;
+pub mod texture;
+pub mod timer;
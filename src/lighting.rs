@@ -0,0 +1,256 @@
+use std::sync::LazyLock;
+
+use cgmath::{InnerSpace, Vector2};
+
+use crate::map::{Light, Map};
+
+/// How quickly surfaces fade with distance, and the brightness they fade toward.
+const FOG_FALLOFF: f32 = 0.16;
+const FOG_MIN_BRIGHTNESS: f32 = 0.2;
+/// The stretch before the view distance over which surfaces fade out, as a fraction of it.
+const VIEW_FADE: f32 = 0.25;
+/// Brightness of wall faces crossed along x and along y, and of diagonal faces between the
+/// two, as if the world were lit from off to one side, so corners read even where there
+/// are no lights.
+const FACE_BRIGHTNESS: [f32; 3] = [1., 0.75, 0.875];
+/// How many steps linear light is held in between decoding and re-encoding sRGB. Every
+/// sRGB byte survives the round trip at this precision.
+const LINEAR_STEPS: usize = 4096;
+
+/// Tables between sRGB-encoded channel bytes and linear light in LINEAR_STEPS steps.
+///
+/// Pixels and textures are sRGB-encoded throughout, and the screen texture is sRGB too, so
+/// the bytes reach the display as they are. Light adds and scales linearly though, so
+/// shading decodes, scales and re-encodes. Scaling the bytes themselves would darken far
+/// more than asked: half of mid-gray's bytes is under a quarter of its light.
+struct SrgbTables {
+    decode: [u16; 256],
+    encode: Vec<u8>,
+}
+
+static SRGB: LazyLock<SrgbTables> = LazyLock::new(|| {
+    let max = (LINEAR_STEPS - 1) as f32;
+    let decode = std::array::from_fn(|byte| {
+        let c = byte as f32 / 255.;
+        let linear = if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        };
+        (linear * max).round() as u16
+    });
+    let encode = (0..LINEAR_STEPS)
+        .map(|step| {
+            let linear = step as f32 / max;
+            let c = if linear <= 0.0031308 {
+                linear * 12.92
+            } else {
+                1.055 * linear.powf(1. / 2.4) - 0.055
+            };
+            (c * 255.).round() as u8
+        })
+        .collect();
+    SrgbTables { decode, encode }
+});
+
+/// Light levels at the center of every map cell, gathered from the map's point lights and
+/// interpolated between cells when sampled. Lights aren't blocked by walls.
+pub struct LightMap {
+    width: usize,
+    height: usize,
+    cells: Vec<[f32; 3]>,
+}
+
+impl LightMap {
+    /// Sums every light at each cell center on top of a uniform `ambient` level.
+    pub fn new(map: &Map, ambient: f32) -> Self {
+        let (width, height) = (map.width(), map.height());
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let center = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let mut level = [ambient; 3];
+                for light in map.lights() {
+                    let falloff = Self::falloff(light, center);
+                    for (channel, color) in level.iter_mut().zip(light.color) {
+                        *channel += color * falloff;
+                    }
+                }
+                cells.push(level.map(|channel| channel.min(1.)));
+            }
+        }
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    /// Fades smoothly from 1 at the light to 0 at its radius.
+    fn falloff(light: &Light, point: Vector2<f32>) -> f32 {
+        let t = ((point - light.position).magnitude() / light.radius).min(1.);
+        (1. - t) * (1. - t)
+    }
+
+    fn cell(&self, x: isize, y: isize) -> [f32; 3] {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.cells[y * self.width + x]
+    }
+
+    /// The light level at a world position, bilinearly filtered between cell centers.
+    pub fn sample(&self, point: Vector2<f32>) -> [f32; 3] {
+        let (fx, fy) = (point.x - 0.5, point.y - 0.5);
+        let (x, y) = (fx.floor(), fy.floor());
+        let (tx, ty) = (fx - x, fy - y);
+        let (x, y) = (x as isize, y as isize);
+        let corners = [
+            (self.cell(x, y), (1. - tx) * (1. - ty)),
+            (self.cell(x + 1, y), tx * (1. - ty)),
+            (self.cell(x, y + 1), (1. - tx) * ty),
+            (self.cell(x + 1, y + 1), tx * ty),
+        ];
+        let mut level = [0.; 3];
+        for (cell, weight) in corners {
+            for (channel, value) in level.iter_mut().zip(cell) {
+                *channel += value * weight;
+            }
+        }
+        level
+    }
+}
+
+/// How much of a surface's brightness survives the distance fog: 1 up close, easing
+/// smoothly toward FOG_MIN_BRIGHTNESS with no kink where it bottoms out.
+pub fn fog(dist: f32) -> f32 {
+    FOG_MIN_BRIGHTNESS + (1. - FOG_MIN_BRIGHTNESS) * (-dist.max(0.) * FOG_FALLOFF).exp()
+}
+
+/// How much of a surface at `dist` shows with the view cut off at `max_dist`: all of it
+/// until the last VIEW_FADE of the way, then easing to none, so the cutoff isn't a hard edge.
+pub fn view_fade(dist: f32, max_dist: f32) -> f32 {
+    if !max_dist.is_finite() {
+        return 1.;
+    }
+    let start = max_dist * (1. - VIEW_FADE);
+    let t = ((max_dist - dist) / (max_dist - start)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+/// The brightness of a wall face, from which way it faces (`side` 0 for faces crossed
+/// along x, 1 along y, 2 for diagonals) and its perpendicular distance.
+pub fn wall_brightness(side: u8, dist: f32) -> f32 {
+    FACE_BRIGHTNESS[side.min(2) as usize] * fog(dist)
+}
+
+/// `color` as seen on a wall face, from which way it faces and its perpendicular distance
+/// as for `wall_brightness`, before any point lights. Alpha is kept.
+pub fn shade(color: u32, side: u8, dist: f32) -> u32 {
+    tint(color, [(wall_brightness(side, dist) * 256.) as u32; 3])
+}
+
+/// Scales the light of each of an sRGB-encoded color's channels by its brightness/256,
+/// keeping alpha. Never brightens past the original color.
+pub fn tint(color: u32, shade: [u32; 3]) -> u32 {
+    let srgb = &*SRGB;
+    let scale = |channel: u8, brightness: u32| {
+        if brightness >= 256 {
+            return channel;
+        }
+        let linear = (srgb.decode[channel as usize] as u32 * brightness) >> 8;
+        srgb.encode[linear as usize]
+    };
+    let [r, g, b, a] = color.to_le_bytes();
+    u32::from_le_bytes([
+        scale(r, shade[0]),
+        scale(g, shade[1]),
+        scale(b, shade[2]),
+        a,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [u32; 4] = [0xFFFFFFFF, 0xFF808080, 0x80FF4020, 0xFF0A0A0A];
+
+    /// Every step of a quarter tile out to well past where the fog bottoms out.
+    fn distances() -> impl Iterator<Item = f32> {
+        (0..200).map(|step| step as f32 * 0.25)
+    }
+
+    #[test]
+    fn faces_crossed_along_x_are_brighter() {
+        for dist in distances() {
+            let [x, ..] = shade(0xFFFFFFFF, 0, dist).to_le_bytes();
+            let [y, ..] = shade(0xFFFFFFFF, 1, dist).to_le_bytes();
+            let [diagonal, ..] = shade(0xFFFFFFFF, 2, dist).to_le_bytes();
+            assert!(x > diagonal && diagonal > y, "{dist}: {x} {diagonal} {y}");
+        }
+    }
+
+    #[test]
+    fn walls_never_brighten_with_distance() {
+        for color in COLORS {
+            for side in 0..3 {
+                let mut last = color.to_le_bytes();
+                for dist in distances() {
+                    let shaded = shade(color, side, dist).to_le_bytes();
+                    assert!(
+                        shaded
+                            .iter()
+                            .zip(last)
+                            .all(|(&channel, last)| channel <= last),
+                        "{color:08X} side {side} at {dist}: {shaded:?} > {last:?}"
+                    );
+                    last = shaded;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shading_never_brightens_and_keeps_alpha() {
+        for color in COLORS {
+            for side in 0..3 {
+                for dist in distances() {
+                    let [r, g, b, a] = shade(color, side, dist).to_le_bytes();
+                    let [r0, g0, b0, a0] = color.to_le_bytes();
+                    assert!(
+                        r <= r0 && g <= g0 && b <= b0,
+                        "{color:08X} side {side} at {dist}"
+                    );
+                    assert_eq!(a, a0);
+                }
+            }
+        }
+        // Right up against an x face is the color itself
+        assert_eq!(shade(0xFF808080, 0, 0.), 0xFF808080);
+    }
+
+    #[test]
+    fn half_brightness_halves_the_light_not_the_bytes() {
+        // Half of mid-gray's light encodes to 92.4, where halving its bytes would give 0x40,
+        // under a quarter of the light
+        let [r, g, b, a] = tint(0xFF808080, [128; 3]).to_le_bytes();
+        assert!(r == g && g == b);
+        assert!((0x5C..=0x5D).contains(&r), "{r:#X}");
+        assert_eq!(a, 0xFF);
+    }
+
+    #[test]
+    fn full_brightness_leaves_colors_alone() {
+        for color in COLORS.into_iter().chain([0x00000000, 0x12345678]) {
+            assert_eq!(tint(color, [256; 3]), color);
+        }
+    }
+
+    #[test]
+    fn every_srgb_byte_survives_the_round_trip() {
+        let srgb = &*SRGB;
+        for byte in 0..=255u8 {
+            assert_eq!(srgb.encode[srgb.decode[byte as usize] as usize], byte);
+        }
+    }
+}
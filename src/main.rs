@@ -1,44 +1,247 @@
-pub mod graphics;
-pub mod renderer;
-
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::{Context, Result};
-use cgmath::{prelude::*, Basis2, Rad, Vector2};
-use graphics::Graphics;
-use renderer::Camera;
+use anyhow::{anyhow, bail, Context, Result};
+use cgmath::{prelude::*, Deg, Rad, Vector2};
+use rust_doom::{
+    camera::Camera,
+    dynamic_resolution::DynamicResolution,
+    graphics::{Graphics, ScalingMode},
+    input::Input,
+    keymap::Action,
+    level::LevelSet,
+    map::Map,
+    save::GameState,
+};
 use winit::{
     event::*,
     event_loop::{EventLoop, EventLoopWindowTarget},
-    keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowBuilder},
+    keyboard::{Key, KeyCode, NamedKey, PhysicalKey},
+    window::{CursorGrabMode, Window, WindowBuilder},
 };
 
 struct State<'a> {
     size: winit::dpi::PhysicalSize<u32>,
     window: &'a Window,
 
-    graphics: graphics::Graphics<'a>,
+    graphics: Graphics<'a>,
     camera: Rc<RefCell<Camera>>,
+    map: Rc<RefCell<Map>>,
+    input: Input,
+    /// Player velocity in world units per second.
+    velocity: Vector2<f32>,
+    /// Freezes the world, and the player in it, while frames keep being drawn.
+    paused: bool,
+    /// Whether the cursor is hidden and held in the window for mouse-look. Clicking grabs it;
+    /// Escape or losing focus lets it go.
+    cursor_grabbed: bool,
+    /// Lets the player move through walls and doors, for inspecting levels.
+    noclip: bool,
+    /// Time not yet simulated, less than one TICK, in seconds.
+    accumulator: f32,
+    /// The camera as of the update before the last, which frames are blended from.
+    previous_camera: Camera,
+    /// When the window title was last refreshed.
+    last_title: Instant,
+}
+
+/// Movement and animation rates are per second, scaled by the frame time.
+const MOVE_SPEED: f32 = 3.;
+/// How quickly the player's velocity approaches MOVE_SPEED while a movement key is held,
+/// and how quickly it decays once they're released. Higher is snappier.
+const ACCELERATION: f32 = 12.;
+const FRICTION: f32 = 8.;
+/// Speed multiplier while Shift is held.
+const SPRINT_MULTIPLIER: f32 = 1.8;
+/// How fast the arrow keys turn the view, tuned separately from the mouse.
+const TURN_SPEED: Rad<f32> = Rad(2.1);
+/// How far a door slides per second, as a fraction of its width.
+const DOOR_SPEED: f32 = 1.8;
+/// The world is advanced in steps of this many seconds, however fast frames are drawn.
+const TICK: f32 = 1. / 60.;
+/// Longest frame time simulated in one frame, so a stall isn't caught up on all at once.
+const MAX_FRAME_TIME: f32 = 0.1;
+/// The camera moving further than this many tiles in one tick has jumped, say by teleporting
+/// or wrapping around the map, and is drawn where it landed rather than swept there.
+const MAX_INTERPOLATED_STEP: f32 = 0.5;
+/// How far in front of the player the use key reaches, in tiles.
+const USE_REACH: f32 = 1.;
+const RENDER_SCALE_STEP: f32 = 0.25;
+/// The frame rate dynamic resolution aims for, and the lowest render scale it may drop to.
+const DYNAMIC_RESOLUTION_FPS: f32 = 60.;
+const DYNAMIC_RESOLUTION_MIN_SCALE: f32 = 0.25;
+/// Where F6 quicksaves to and F9 quickloads from.
+const SAVE_PATH: &str = "quicksave.sav";
+/// Pixels rendered per screen pixel along each axis while supersampling is on.
+const SUPERSAMPLE_FACTOR: u32 = 2;
+/// Horizontal FOV bounds for the zoom wheel. Zero would collapse the view plane, and 180
+/// would stretch it to infinity.
+const MIN_FOV: Deg<f32> = Deg(20.);
+const MAX_FOV: Deg<f32> = Deg(140.);
+/// How far one notch of the wheel zooms.
+const FOV_PER_LINE: Deg<f32> = Deg(5.);
+/// Pixels of touchpad scrolling that count as one notch of the wheel.
+const PIXELS_PER_LINE: f32 = 20.;
+/// Floor colors cycled through by the floor color key, starting from the default gray.
+const FLOOR_COLORS: [u32; 4] = [0xFF404040, 0xFF203050, 0xFF204020, 0xFF502020];
+/// How fast the eye rises or sinks while the raise or lower key is held, in units per second.
+const EYE_SPEED: f32 = 0.5;
+/// How far the view tilts per pixel of vertical mouse movement, in screen heights.
+const MOUSE_PITCH_SENSITIVITY: f32 = 0.002;
+/// The scaling modes Z cycles through: stretched, then letterboxed to 4:3 and to 16:9.
+const SCALING_MODES: [ScalingMode; 3] = [
+    ScalingMode::Stretch,
+    ScalingMode::Letterbox(4. / 3.),
+    ScalingMode::Letterbox(16. / 9.),
+];
+/// Present modes cycled through by the vsync key: capped, uncapped without tearing, uncapped.
+const PRESENT_MODES: [wgpu::PresentMode; 3] = [
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Mailbox,
+    wgpu::PresentMode::Immediate,
+];
+/// Frame rate caps cycled through by the limit key, as the shortest time a frame may take.
+const FRAME_CAPS: [Option<Duration>; 3] = [
+    None,
+    Some(Duration::from_nanos(1_000_000_000 / 60)),
+    Some(Duration::from_nanos(1_000_000_000 / 30)),
+];
+
+const TITLE: &str = "Rust Doom";
+/// How often the window title's frame rate and position readout is refreshed.
+const TITLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A console command: what's typed to run it, the arguments it takes, and what it does.
+/// `run` gets the words after the name, and returns a line to print if it has one.
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    run: fn(&mut State, &[&str]) -> Result<Option<String>>,
 }
 
+/// Every command the console knows, in the order help lists them.
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "help",
+        run: |_, _| {
+            let usages: Vec<_> = COMMANDS.iter().map(|command| command.usage).collect();
+            Ok(Some(usages.join("\n")))
+        },
+    },
+    Command {
+        name: "fov",
+        usage: "fov <degrees>",
+        run: |state, args| {
+            let [degrees] = args else {
+                bail!("usage: fov <degrees>");
+            };
+            let degrees: f32 = degrees.parse().context("usage: fov <degrees>")?;
+            let mut camera = state.camera.borrow_mut();
+            camera.set_fov(Deg(degrees.clamp(MIN_FOV.0, MAX_FOV.0)));
+            Ok(Some(format!("fov {:.0}", camera.fov().0)))
+        },
+    },
+    Command {
+        name: "noclip",
+        usage: "noclip",
+        run: |state, _| {
+            state.noclip = !state.noclip;
+            Ok(Some(format!(
+                "noclip {}",
+                if state.noclip { "on" } else { "off" }
+            )))
+        },
+    },
+    Command {
+        name: "teleport",
+        usage: "teleport <x> <y>",
+        run: |state, args| {
+            let [x, y] = args else {
+                bail!("usage: teleport <x> <y>");
+            };
+            let (x, y): (f32, f32) = (
+                x.parse().context("usage: teleport <x> <y>")?,
+                y.parse().context("usage: teleport <x> <y>")?,
+            );
+            let map = state.map.borrow();
+            let (width, height) = (map.width() as f32, map.height() as f32);
+            if !(0. ..width).contains(&x) || !(0. ..height).contains(&y) {
+                bail!("({x}, {y}) is off the {width}x{height} map");
+            }
+            if map.is_wall(x, y) && !state.noclip {
+                bail!("({x}, {y}) is inside a wall");
+            }
+            state.camera.borrow_mut().player_pos = Vector2::new(x, y);
+            state.velocity = Vector2::zero();
+            Ok(None)
+        },
+    },
+    Command {
+        name: "load",
+        usage: "load <map file>",
+        run: |state, args| {
+            let [path] = args else {
+                bail!("usage: load <map file>");
+            };
+            let map = Map::load(path)?;
+            let renderer = state.graphics.renderer_mut();
+            let level = renderer.levels_mut().push(map);
+            renderer.load_level(level)?;
+            state.velocity = Vector2::zero();
+            Ok(Some(format!("loaded {path} as level {level}")))
+        },
+    },
+    Command {
+        name: "level",
+        usage: "level <number>",
+        run: |state, args| {
+            let [level] = args else {
+                bail!("usage: level <number>");
+            };
+            let level: usize = level.parse().context("usage: level <number>")?;
+            state.graphics.renderer_mut().load_level(level)?;
+            state.velocity = Vector2::zero();
+            Ok(Some(format!("loaded level {level}")))
+        },
+    },
+];
+
 impl<'a> State<'a> {
     // Creating some of the wgpu types requires async code
-    async fn new(window: &'a Window) -> Result<State<'a>> {
+    async fn new(window: &'a Window, levels: LevelSet) -> Result<State<'a>> {
         let size = window.inner_size();
-        let camera = Rc::new(RefCell::new(Camera {
-            player_pos: Vector2::new(5., 5.),
-            facing_dir: Vector2::new(-1., 0.1),
-            view_plane: Vector2::new(0., 0.66),
-        }));
-        let graphics = Graphics::new(camera.clone(), window, size)
+        // A unit-tall wall one unit away fills the screen height when the plane is half the aspect ratio
+        let aspect = size.width as f32 / size.height.max(1) as f32;
+        let level = levels.current_map();
+        let camera = Rc::new(RefCell::new(Camera::new(
+            level.spawn_pos(),
+            level.spawn_dir(),
+            0.5 * aspect,
+        )));
+        let previous_camera = *camera.borrow();
+        let map = Rc::new(RefCell::new(levels.current_map().clone()));
+        let mut graphics = Graphics::new(camera.clone(), map.clone(), window, size)
             .await
             .context("failed to construct graphics")?;
+        graphics.renderer_mut().set_levels(levels);
         Ok(State {
             size,
             window,
             graphics,
             camera,
+            map,
+            input: Input::default(),
+            velocity: Vector2::zero(),
+            paused: false,
+            cursor_grabbed: false,
+            noclip: false,
+            accumulator: 0.,
+            previous_camera,
+            last_title: Instant::now(),
         })
     }
 
@@ -48,14 +251,23 @@ impl<'a> State<'a> {
                 ref event,
                 window_id,
             } => {
-                if window_id == self.window.id() {
-                    if self.input(&event) {
-                        return;
-                    }
-                    if !self.handle_event(&event) {
-                        control_flow.exit();
-                    }
+                if window_id != self.window.id() || self.input(event) {
+                    return;
                 }
+                if !self.handle_event(event) {
+                    control_flow.exit();
+                }
+            }
+            // Raw motion rather than the cursor position, so looking isn't stopped by the
+            // cursor reaching the edge of the window. Moving the mouse up looks up. Motion
+            // only looks while the cursor is grabbed, so moving it over the window doesn't.
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta: (_, dy) },
+                ..
+            } if self.cursor_grabbed && !self.paused => {
+                self.camera
+                    .borrow_mut()
+                    .look_up(-dy as f32 * MOUSE_PITCH_SENSITIVITY);
             }
             _ => {}
         }
@@ -66,10 +278,18 @@ impl<'a> State<'a> {
             WindowEvent::Resized(physical_size) => {
                 self.resize(*physical_size);
             }
+            // Nothing is drawn while minimized; resizing back requests the next frame
+            WindowEvent::RedrawRequested if self.is_minimized() => {}
             WindowEvent::RedrawRequested => {
                 self.window().request_redraw();
-                self.update();
-                match self.render() {
+                let frame_time = self.graphics.timer().frame_time().as_secs_f32();
+                self.accumulator += frame_time.min(MAX_FRAME_TIME);
+                while self.accumulator >= TICK {
+                    self.previous_camera = *self.camera.borrow();
+                    self.update(TICK);
+                    self.accumulator -= TICK;
+                }
+                match self.render(self.accumulator / TICK) {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
@@ -86,66 +306,529 @@ impl<'a> State<'a> {
                         log::warn!("Surface timeout")
                     }
                 }
+                self.update_title();
             }
-            _ if is_close_event(event) => return false,
+            _ if self.is_close_event(event) => return false,
             _ => {}
         }
         true
     }
 
     pub fn window(&self) -> &Window {
-        &self.window
+        self.window
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.graphics.resize(new_size);
+        let was_minimized = self.is_minimized();
+        self.size = new_size;
+        if self.is_minimized() {
+            return;
         }
+        self.graphics.resize(new_size);
+        if was_minimized {
+            self.graphics.timer_mut().reset();
+            self.window.request_redraw();
+        }
+    }
+
+    /// Whether the window has no area to draw into, as on some platforms when minimized.
+    fn is_minimized(&self) -> bool {
+        self.size.width == 0 || self.size.height == 0
     }
 
-    fn input(&mut self, _event: &WindowEvent) -> bool {
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } if self.graphics.console().is_open() => {
+                return self.console_key(event);
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                self.input.handle_key(event);
+                if let (ElementState::Pressed, false, PhysicalKey::Code(code)) =
+                    (event.state, event.repeat, event.physical_key)
+                {
+                    return self.key_pressed(code);
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                self.zoom(lines);
+                return true;
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                ..
+            } => {
+                self.set_cursor_grabbed(true);
+                return true;
+            }
+            WindowEvent::Focused(false) => {
+                self.input.clear();
+                self.set_cursor_grabbed(false);
+            }
+            _ => {}
+        }
         false
     }
 
-    fn update(&mut self) {
+    /// Hides the cursor and holds it in the window for mouse-look, or frees and shows it.
+    fn set_cursor_grabbed(&mut self, grabbed: bool) {
+        if grabbed == self.cursor_grabbed {
+            return;
+        }
+        let result = if grabbed {
+            // Platforms support one mode or the other: macOS only locks, Windows only confines
+            self.window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            self.window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(err) = result {
+            log::warn!("failed to change cursor grab: {err}");
+            return;
+        }
+        self.window.set_cursor_visible(!grabbed);
+        self.cursor_grabbed = grabbed;
+    }
+
+    /// Narrows the field of view by `lines` notches of the wheel, or widens it if negative.
+    fn zoom(&mut self, lines: f32) {
+        let mut camera = self.camera.borrow_mut();
+        let fov = camera.fov() - FOV_PER_LINE * lines;
+        camera.set_fov(Deg(fov.0.clamp(MIN_FOV.0, MAX_FOV.0)));
+        log::debug!("fov: {:.0}°", camera.fov().0);
+    }
+
+    /// Handles one-shot key actions. Returns true if the key was consumed.
+    fn key_pressed(&mut self, code: KeyCode) -> bool {
+        let Some(action) = self.input.action(code) else {
+            return false;
+        };
+        match action {
+            Action::DecreaseRenderScale => {
+                self.graphics.set_dynamic_resolution(None);
+                let scale = self.graphics.render_scale() - RENDER_SCALE_STEP;
+                self.graphics.set_render_scale(scale);
+            }
+            Action::IncreaseRenderScale => {
+                self.graphics.set_dynamic_resolution(None);
+                let scale = self.graphics.render_scale() + RENDER_SCALE_STEP;
+                self.graphics.set_render_scale(scale);
+            }
+            Action::ToggleDynamicResolution => {
+                let controller = match self.graphics.dynamic_resolution() {
+                    Some(_) => None,
+                    None => Some(
+                        DynamicResolution::new(
+                            DYNAMIC_RESOLUTION_FPS,
+                            DYNAMIC_RESOLUTION_MIN_SCALE,
+                            1.,
+                        )
+                        .with_scale(self.graphics.render_scale()),
+                    ),
+                };
+                let enabled = controller.is_some();
+                self.graphics.set_dynamic_resolution(controller);
+                log::info!("dynamic resolution: {}", if enabled { "on" } else { "off" });
+            }
+            Action::ToggleMinimap => {
+                let renderer = self.graphics.renderer_mut();
+                renderer.set_show_minimap(!renderer.show_minimap());
+            }
+            Action::ToggleSky => {
+                let renderer = self.graphics.renderer_mut();
+                renderer.set_show_sky(!renderer.show_sky());
+            }
+            Action::ToggleRays => {
+                let renderer = self.graphics.renderer_mut();
+                renderer.set_show_rays(!renderer.show_rays());
+            }
+            Action::CyclePresentMode => {
+                let current = PRESENT_MODES
+                    .iter()
+                    .position(|&mode| mode == self.graphics.present_mode())
+                    .unwrap_or(0);
+                let next = (1..=PRESENT_MODES.len())
+                    .map(|i| PRESENT_MODES[(current + i) % PRESENT_MODES.len()])
+                    .find(|&mode| self.graphics.supports_present_mode(mode))
+                    .unwrap_or(wgpu::PresentMode::Fifo);
+                let mode = self.graphics.set_present_mode(next);
+                log::info!("present mode: {mode:?}");
+            }
+            Action::CycleScalingMode => {
+                let current = SCALING_MODES
+                    .iter()
+                    .position(|&mode| mode == self.graphics.scaling_mode())
+                    .unwrap_or(0);
+                let next = SCALING_MODES[(current + 1) % SCALING_MODES.len()];
+                self.graphics.set_scaling_mode(next);
+                log::info!("scaling mode: {next:?}");
+            }
+            Action::CycleFrameCap => {
+                let timer = self.graphics.timer_mut();
+                let current = FRAME_CAPS
+                    .iter()
+                    .position(|&cap| cap == timer.target())
+                    .unwrap_or(0);
+                let next = FRAME_CAPS[(current + 1) % FRAME_CAPS.len()];
+                timer.set_target(next);
+                log::info!("frame cap: {next:?}");
+            }
+            Action::CycleFloorColor => {
+                let renderer = self.graphics.renderer_mut();
+                let mut config = *renderer.config();
+                let current = FLOOR_COLORS
+                    .iter()
+                    .position(|&color| color == config.floor_color)
+                    .unwrap_or(0);
+                config.floor_color = FLOOR_COLORS[(current + 1) % FLOOR_COLORS.len()];
+                renderer.set_config(config);
+            }
+            Action::Use => self.use_door(),
+            Action::NextLevel => {
+                let renderer = self.graphics.renderer_mut();
+                let next = (renderer.levels().current() + 1) % renderer.levels().len();
+                match renderer.load_level(next) {
+                    Ok(()) => {
+                        self.velocity = Vector2::zero();
+                        log::info!("loaded level {next}");
+                    }
+                    Err(err) => log::error!("{err:#}"),
+                }
+            }
+            Action::Pause => {
+                self.paused = !self.paused;
+                log::info!("{}", if self.paused { "paused" } else { "resumed" });
+            }
+            Action::CycleRenderMode => {
+                let renderer = self.graphics.renderer_mut();
+                let mode = renderer.render_mode().next();
+                renderer.set_render_mode(mode);
+                log::info!("render mode: {mode:?}");
+            }
+            Action::ToggleNoclip => {
+                self.noclip = !self.noclip;
+                log::info!("noclip: {}", if self.noclip { "on" } else { "off" });
+            }
+            Action::ReleaseCursor => self.set_cursor_grabbed(false),
+            Action::Screenshot => self.screenshot(),
+            Action::Quicksave => self.quicksave(),
+            Action::Quickload => self.quickload(),
+            Action::ToggleRecording => self.toggle_recording(),
+            Action::ToggleGpuRaycast => {
+                let enabled = !self.graphics.gpu_raycast();
+                self.graphics.set_gpu_raycast(enabled);
+            }
+            Action::ToggleConsole => {
+                // The backquote that opened it shouldn't be the first thing typed
+                self.input.take_text();
+                self.input.clear();
+                self.graphics.console_mut().set_open(true);
+            }
+            Action::ToggleSupersampling => {
+                let renderer = self.graphics.renderer_mut();
+                let factor = if renderer.supersample() == 1 {
+                    SUPERSAMPLE_FACTOR
+                } else {
+                    1
+                };
+                renderer.set_supersample(factor);
+                log::info!("supersampling: {factor}x");
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Handles a key while the console is open, which takes every key but Quit.
+    fn console_key(&mut self, event: &KeyEvent) -> bool {
+        self.input.handle_key(event);
+        // Keys held at the console mustn't also walk the player around
+        self.input.clear();
+        let text = self.input.take_text();
+        let console = self.graphics.console_mut();
+        if event.state == ElementState::Released {
+            return true;
+        }
+        match event.logical_key {
+            Key::Named(NamedKey::Backspace) => console.backspace(),
+            Key::Named(NamedKey::Enter) => {
+                if let Some(line) = console.submit() {
+                    self.run_command(&line);
+                }
+            }
+            Key::Named(NamedKey::Escape) => console.set_open(false),
+            _ => match event.physical_key {
+                PhysicalKey::Code(code) if self.input.action(code) == Some(Action::Quit) => {
+                    return false;
+                }
+                PhysicalKey::Code(code)
+                    if self.input.action(code) == Some(Action::ToggleConsole) =>
+                {
+                    if !event.repeat {
+                        console.set_open(false);
+                    }
+                }
+                _ => console.type_text(&text),
+            },
+        }
+        true
+    }
+
+    /// Runs a line typed at the console, printing what it says back to it.
+    fn run_command(&mut self, line: &str) {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return;
+        };
+        let args: Vec<_> = words.collect();
+        let result = match COMMANDS.iter().find(|command| command.name == name) {
+            Some(command) => (command.run)(self, &args),
+            None => Err(anyhow!("unknown command {name:?}, try help")),
+        };
+        let console = self.graphics.console_mut();
+        match result {
+            Ok(Some(output)) => console.print(output),
+            Ok(None) => {}
+            Err(err) => console.print_error(format!("{err:#}")),
+        }
+    }
+
+    /// Toggles the door the player is facing, if one is within reach.
+    fn use_door(&mut self) {
+        let camera = self.camera.borrow();
+        let mut map = self.map.borrow_mut();
+        let target = map.wrap_position(camera.position() + camera.forward() * USE_REACH);
+        if target.x >= 0. && target.y >= 0. {
+            map.toggle_door(target.x as usize, target.y as usize);
+        }
+    }
+
+    fn quicksave(&self) {
+        let level = self.graphics.renderer().levels().current();
+        let state = GameState::capture(&self.camera.borrow(), &self.map.borrow(), level);
+        match state.save(SAVE_PATH) {
+            Ok(()) => log::info!("saved {SAVE_PATH}"),
+            Err(err) => log::error!("{err:#}"),
+        }
+    }
+
+    /// Reloads the saved level from scratch, then puts the player and its doors back as
+    /// they were saved.
+    fn quickload(&mut self) {
+        let result = GameState::load(SAVE_PATH).and_then(|state| {
+            self.graphics.renderer_mut().load_level(state.level)?;
+            state.apply(&mut self.camera.borrow_mut(), &mut self.map.borrow_mut());
+            Ok(())
+        });
+        match result {
+            Ok(()) => {
+                self.velocity = Vector2::zero();
+                log::info!("loaded {SAVE_PATH}");
+            }
+            Err(err) => log::error!("{err:#}"),
+        }
+    }
+
+    fn screenshot(&self) {
+        // The compute shader draws straight into a GPU texture, leaving the CPU frame stale
+        if self.graphics.gpu_raycast() {
+            log::warn!("screenshots need the CPU renderer; toggle GPU raycasting off first");
+            return;
+        }
+        let path = format!("screenshot-{}.png", timestamp());
+        match self.graphics.renderer().save_screenshot(&path) {
+            Ok(()) => log::info!("saved {path}"),
+            Err(err) => log::error!("{err:#}"),
+        }
+    }
+
+    /// Starts saving every frame into a new directory, or stops if already recording.
+    fn toggle_recording(&mut self) {
+        let renderer = self.graphics.renderer_mut();
+        if let Some(frames) = renderer.disable_recording() {
+            log::info!("stopped recording after {frames} frames");
+            return;
+        }
+        let dir = format!("recording-{}", timestamp());
+        match renderer.enable_recording(&dir) {
+            Ok(()) => log::info!("recording to {dir}"),
+            Err(err) => log::error!("{err:#}"),
+        }
+    }
+
+    /// Advances the world by `dt` seconds.
+    fn update(&mut self, dt: f32) {
+        if self.paused {
+            return;
+        }
+
         let mut camera = self.camera.borrow_mut();
-        let angle = Rad(0.007); //0.005f32;
-        let rot: Basis2<f32> = Rotation2::from_angle(angle);
-        camera.facing_dir = rot.rotate_vector(camera.facing_dir);
-        camera.view_plane = rot.rotate_vector(camera.view_plane);
+        // A positive angle turns from the facing direction away from the view plane, to the left
+        if self.input.is_active(Action::TurnLeft) {
+            camera.rotate(TURN_SPEED * dt);
+        }
+        if self.input.is_active(Action::TurnRight) {
+            camera.rotate(-TURN_SPEED * dt);
+        }
+
+        if self.input.is_active(Action::RaiseEye) {
+            camera.raise(EYE_SPEED * dt);
+        }
+        if self.input.is_active(Action::LowerEye) {
+            camera.raise(-EYE_SPEED * dt);
+        }
+
+        let pos = camera.position();
+        let occupied = (pos.x as usize, pos.y as usize);
+        let mut map = self.map.borrow_mut();
+        map.update_doors(DOOR_SPEED * dt, occupied);
+        map.update_animations(dt);
+        drop(map);
+
+        // Where a unit of each held move takes the camera from the origin, which is the way
+        // the player wants to go
+        let mut moved = Camera {
+            player_pos: Vector2::zero(),
+            ..*camera
+        };
+        if self.input.is_active(Action::MoveForward) {
+            moved.move_forward(1.);
+        }
+        if self.input.is_active(Action::MoveBackward) {
+            moved.move_forward(-1.);
+        }
+        if self.input.is_active(Action::StrafeRight) {
+            moved.strafe(1.);
+        }
+        if self.input.is_active(Action::StrafeLeft) {
+            moved.strafe(-1.);
+        }
+        let wish = moved.player_pos;
+
+        let speed = if self.input.is_active(Action::Sprint) {
+            MOVE_SPEED * SPRINT_MULTIPLIER
+        } else {
+            MOVE_SPEED
+        };
+
+        // Ease the velocity toward the wished-for one: quickly while a key is held, and more
+        // gently as friction once they're all released. The exponential keeps this the same
+        // at any frame rate.
+        let (target, rate) = if wish.magnitude2() > 0. {
+            // Normalized first so sprinting diagonally is no faster than straight ahead
+            (wish.normalize() * speed, ACCELERATION)
+        } else {
+            (Vector2::zero(), FRICTION)
+        };
+        self.velocity += (target - self.velocity) * (1. - (-rate * dt).exp());
+
+        let map = self.map.borrow();
+        // Velocity is in world units, and positions are in tiles
+        let step = self.velocity * dt / map.world_scale();
+        if self.noclip {
+            camera.player_pos += step;
+        } else {
+            // Each axis is blocked separately so the player slides along walls, and loses the
+            // velocity pushing into them
+            if map.is_wall(pos.x + step.x, pos.y) {
+                self.velocity.x = 0.;
+            } else {
+                camera.player_pos.x += step.x;
+            }
+            if map.is_wall(camera.player_pos.x, pos.y + step.y) {
+                self.velocity.y = 0.;
+            } else {
+                camera.player_pos.y += step.y;
+            }
+        }
+        let walked = (camera.player_pos - pos).magnitude() * map.world_scale();
+        // A wrapping map brings the player back in at the far edge. Noclip has no walls to
+        // stop them leaving an open-edged map, so it clamps too, as rays must start on it
+        camera.player_pos = if self.noclip {
+            map.clamp_position(camera.player_pos)
+        } else {
+            map.wrap_position(camera.player_pos)
+        };
+        self.graphics.renderer_mut().walk(walked);
+    }
+
+    /// Draws the world `alpha` of the way from the update before last to the last one, so
+    /// motion is smooth at frame rates other than the update rate.
+    fn render(&mut self, alpha: f32) -> std::result::Result<(), wgpu::SurfaceError> {
+        let current = *self.camera.borrow();
+        let jumped = (current.player_pos - self.previous_camera.player_pos).magnitude()
+            > MAX_INTERPOLATED_STEP;
+        let drawn = if jumped {
+            current
+        } else {
+            self.previous_camera.lerp(&current, alpha)
+        };
+        self.graphics.render(drawn)
+    }
+
+    /// Shows the frame rate and player position in the window title, at most every
+    /// TITLE_INTERVAL since setting the title can be slow on some platforms.
+    fn update_title(&mut self) {
+        if self.last_title.elapsed() < TITLE_INTERVAL {
+            return;
+        }
+        self.last_title = Instant::now();
+        let position = self.camera.borrow().position();
+        self.window.set_title(&format!(
+            "{TITLE} - {:.0} fps - ({:.1}, {:.1})",
+            self.graphics.timer().fps(),
+            position.x,
+            position.y
+        ));
     }
 
-    fn render(&mut self) -> std::result::Result<(), wgpu::SurfaceError> {
-        self.graphics.render()
+    /// Closing the window or pressing a key bound to Quit.
+    fn is_close_event(&self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CloseRequested => true,
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(code),
+                        ..
+                    },
+                ..
+            } => self.input.action(*code) == Some(Action::Quit),
+            _ => false,
+        }
     }
 }
 
-fn is_close_event(event: &WindowEvent) -> bool {
-    return match event {
-        WindowEvent::CloseRequested => true,
-        WindowEvent::KeyboardInput {
-            event:
-                KeyEvent {
-                    state: ElementState::Pressed,
-                    physical_key: PhysicalKey::Code(KeyCode::Escape),
-                    ..
-                },
-            ..
-        } => true,
-        _ => false,
-    };
+/// Milliseconds since the Unix epoch, for naming saved files.
+fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
 }
 
 async fn run() -> Result<()> {
     env_logger::init();
+    // Every argument is a level, switched between at runtime
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    let levels = if paths.is_empty() {
+        LevelSet::default()
+    } else {
+        LevelSet::load(paths)?
+    };
+
     let event_loop = EventLoop::new().context("failed to construct event loop")?;
     let window = WindowBuilder::new()
-        .with_title("Rust Doom")
+        .with_title(TITLE)
         .build(&event_loop)
         .context("failed to construct window")?;
 
-    let mut state = State::new(&window)
+    let mut state = State::new(&window, levels)
         .await
         .context("failed to construct state")?;
 
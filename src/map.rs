@@ -0,0 +1,1285 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+    fs,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{bail, Context, Result};
+use cgmath::Vector2;
+use serde::{Deserialize, Serialize};
+
+use crate::{dda::Dda, json::Value};
+
+#[rustfmt::skip]
+const MAP_DATA: [u8; 15*15] = [
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 2, 0, 0, 3, 3, 3, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+];
+
+/// Tile id of a sliding door. Doors are drawn as a thin wall through the middle of their cell.
+pub const DOOR_TILE: u8 = 4;
+/// Tile id of a thin wall: a fixed panel through the middle of its cell, for pillars and
+/// door frames. Doors are thin walls that can slide aside.
+pub const THIN_WALL_TILE: u8 = 5;
+/// Tile id of a window. Windows block movement but rays see through them.
+pub const WINDOW_TILE: u8 = 9;
+/// How open a door has to be before the player fits through.
+const DOOR_PASSABLE: f32 = 0.9;
+/// How far inside the map's edges `clamp_position` keeps positions, so they floor to a tile
+/// on the map rather than to the one just past its far edge.
+const EDGE_MARGIN: f32 = 0.001;
+
+/// The animated state of one door tile.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Door {
+    /// How far the door has slid aside, from 0 (closed) to 1 (open).
+    pub open: f32,
+    pub opening: bool,
+}
+
+/// A tile drawn with other tiles' textures in turn, for flickering lights and pulsing panels.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    /// The tile ids whose textures (or colors, untextured) are shown, in order.
+    frames: Vec<u8>,
+    /// How long each frame is shown, in seconds.
+    frame_time: f32,
+    elapsed: f32,
+}
+
+impl Animation {
+    /// An animation starting on its first frame. Fails without frames or with a frame time
+    /// that isn't positive.
+    pub fn new(frames: Vec<u8>, frame_time: f32) -> Result<Self> {
+        if frames.is_empty() {
+            bail!("an animation needs at least one frame");
+        }
+        if frame_time.is_nan() || frame_time <= 0. {
+            bail!("animation frame time {frame_time} should be positive");
+        }
+        Ok(Self {
+            frames,
+            frame_time,
+            elapsed: 0.,
+        })
+    }
+
+    /// The tile id of the frame showing now.
+    pub fn frame(&self) -> u8 {
+        let index = (self.elapsed / self.frame_time) as usize;
+        self.frames[index.min(self.frames.len() - 1)]
+    }
+
+    /// Moves the animation on by `dt` seconds, looping back to the first frame after the last.
+    pub fn advance(&mut self, dt: f32) {
+        self.elapsed = (self.elapsed + dt) % (self.frame_time * self.frames.len() as f32);
+    }
+}
+
+/// A floor tile drawn as a flat color whose brightness ripples over time, such as water or
+/// lava, in place of the floor texture.
+#[derive(Clone, Copy, Debug)]
+pub struct Liquid {
+    /// The color at full brightness, in the pixel format.
+    pub color: u32,
+    /// How far the brightness dips, from 0 (steady) to 1 (down to black).
+    pub ripple: f32,
+    /// Ripples per second.
+    pub speed: f32,
+    /// Ripples per tile. At 0 the whole surface pulses together, like a glow.
+    pub scale: f32,
+}
+
+impl Liquid {
+    /// The brightness in [0, 1] at a world position, `time` seconds in.
+    pub fn brightness(&self, pos: Vector2<f32>, time: f32) -> f32 {
+        let wave = |coord: f32| (TAU * (coord * self.scale + time * self.speed)).sin();
+        1. - self.ripple * (0.5 + 0.25 * (wave(pos.x) + wave(pos.y)))
+    }
+}
+
+/// A wall cutting its cell diagonally corner to corner, named for the corner it fills as
+/// the map is laid out (x rightward, y downward). The filled half blocks movement, and its
+/// two outer edges and the diagonal face are drawn as walls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Diagonal {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Diagonal {
+    /// Parses a corner name: `top-left`, `top-right`, `bottom-left` or `bottom-right`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "top-left" => Some(Diagonal::TopLeft),
+            "top-right" => Some(Diagonal::TopRight),
+            "bottom-left" => Some(Diagonal::BottomLeft),
+            "bottom-right" => Some(Diagonal::BottomRight),
+            _ => None,
+        }
+    }
+
+    /// Whether a point in the cell, given relative to its top-left corner, lies in the
+    /// filled half. Points on the diagonal itself don't.
+    pub fn is_solid(self, local: Vector2<f32>) -> bool {
+        match self {
+            Diagonal::TopLeft => local.x + local.y < 1.,
+            Diagonal::BottomRight => local.x + local.y > 1.,
+            Diagonal::TopRight => local.x > local.y,
+            Diagonal::BottomLeft => local.y > local.x,
+        }
+    }
+
+    /// How far along `dir`, in multiples of it, a ray from `local` (relative to the cell's
+    /// top-left corner) meets the line of the diagonal. None if it runs parallel.
+    pub fn intersect(self, local: Vector2<f32>, dir: Vector2<f32>) -> Option<f32> {
+        let dist = match self {
+            // The wall runs from the top-right corner to the bottom-left, where x + y = 1
+            Diagonal::TopLeft | Diagonal::BottomRight => (1. - local.x - local.y) / (dir.x + dir.y),
+            // and from the top-left to the bottom-right, where x = y
+            Diagonal::TopRight | Diagonal::BottomLeft => (local.y - local.x) / (dir.x - dir.y),
+        };
+        dist.is_finite().then_some(dist)
+    }
+
+    /// Where along the diagonal face a point on it lies, in [0, 1), running left to right
+    /// as seen from the open side so textures aren't mirrored.
+    pub fn wall_x(self, local: Vector2<f32>) -> f32 {
+        let along = local.x.clamp(0., 1.);
+        match self {
+            Diagonal::BottomRight | Diagonal::BottomLeft => along,
+            Diagonal::TopLeft | Diagonal::TopRight => 1. - along,
+        }
+        .min(1. - f32::EPSILON)
+    }
+}
+
+/// Which side of its cell a wall face is on, as the map is laid out: north is up the map,
+/// toward smaller y, and east is right, toward larger x.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Face {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Face {
+    /// The face a ray heading along `dir` hits on crossing a grid line of constant x (`side`
+    /// 0) or of constant y (1). A ray heading east meets a wall's west face. Diagonal faces
+    /// are none of the four.
+    pub fn hit(side: u8, dir: Vector2<f32>) -> Option<Face> {
+        match side {
+            0 if dir.x > 0. => Some(Face::West),
+            0 => Some(Face::East),
+            1 if dir.y > 0. => Some(Face::North),
+            1 => Some(Face::South),
+            _ => None,
+        }
+    }
+}
+
+/// A colored point light, shining equally in every direction and fading out by `radius`.
+#[derive(Clone, Copy)]
+pub struct Light {
+    pub position: Vector2<f32>,
+    /// Linear RGB intensity, each channel usually in [0, 1].
+    pub color: [f32; 3],
+    pub radius: f32,
+}
+
+/// A camera-facing billboard standing on the floor at a world position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub position: Vector2<f32>,
+    /// Index into the renderer's sprite textures.
+    pub texture: usize,
+}
+
+/// The next map revision to hand out. Shared by every map, so no two maps' revisions ever
+/// match unless one is a clone of the other.
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A rectangular grid of tile ids, stored row-major. Tile 0 is empty space.
+///
+/// The edge needn't be walled in. A ray that leaves the map hits nothing, so its column is
+/// open floor below the horizon and sky or ceiling above it, and the player can't walk off.
+/// A wrapping map joins its edges instead, so walking or looking off one side comes back in
+/// on the other.
+#[derive(Clone)]
+pub struct Map {
+    width: usize,
+    height: usize,
+    tiles: Vec<u8>,
+    /// Wall height multiplier per tile id; ids not listed are one unit tall.
+    tile_heights: HashMap<u8, f32>,
+    /// Mutable state for every door tile, keyed by cell.
+    doors: HashMap<(usize, usize), Door>,
+    spawn_pos: Vector2<f32>,
+    spawn_dir: Vector2<f32>,
+    lights: Vec<Light>,
+    /// Tile ids that rays carry on through, drawing them blended over what's behind.
+    see_through: HashSet<u8>,
+    /// Flat colors for tile ids, in the pixel format, overriding the renderer's defaults.
+    tile_colors: HashMap<u8, u32>,
+    /// Where sprites stand when the map is loaded.
+    sprites: Vec<Sprite>,
+    /// Tile ids whose texture cycles over time. Tiles without one keep their own.
+    animations: HashMap<u8, Animation>,
+    /// World units along each side of a tile. Positions stay in tiles, but walls, sprites and
+    /// the eye are measured in world units, so a larger scale makes the same grid feel
+    /// bigger and slower to cross.
+    world_scale: f32,
+    /// Whether the map wraps around at its edges like a torus.
+    wrap: bool,
+    /// Floor tile ids, laid out like `tiles` but independent of the walls. None until one
+    /// is set, leaving the whole floor plain.
+    floors: Option<Vec<u8>>,
+    /// How floor tile ids are drawn. Ids without one are plain floor.
+    liquids: HashMap<u8, Liquid>,
+    /// Seconds of animation so far, for the liquids.
+    time: f32,
+    /// Tile ids that fill only half their cell, cut along a diagonal.
+    diagonals: HashMap<u8, Diagonal>,
+    /// Tile ids whose faces are drawn as other tile ids, in north, south, east, west order.
+    faces: HashMap<u8, [u8; 4]>,
+    /// Changed whenever what a ray would hit does. See `revision`.
+    revision: u64,
+    /// Changed whenever a light is added. See `lights_revision`.
+    lights_revision: u64,
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Self {
+            width: 15,
+            height: 15,
+            tiles: MAP_DATA.to_vec(),
+            tile_heights: HashMap::new(),
+            doors: HashMap::new(),
+            spawn_pos: Vector2::new(5., 5.),
+            spawn_dir: Vector2::new(-1., 0.1),
+            lights: Vec::new(),
+            see_through: HashSet::from([WINDOW_TILE]),
+            tile_colors: HashMap::new(),
+            sprites: [(3.5, 3.5, 0), (7.5, 5.5, 1), (6.5, 8.5, 0), (10.5, 11.5, 1)]
+                .into_iter()
+                .map(|(x, y, texture)| Sprite {
+                    position: Vector2::new(x, y),
+                    texture,
+                })
+                .collect(),
+            animations: HashMap::new(),
+            world_scale: 1.,
+            wrap: false,
+            floors: None,
+            liquids: HashMap::new(),
+            time: 0.,
+            diagonals: HashMap::new(),
+            faces: HashMap::new(),
+            revision: next_revision(),
+            lights_revision: next_revision(),
+        }
+    }
+}
+
+impl Map {
+    /// A map with the player spawning in its center, facing along -x.
+    pub fn new(width: usize, height: usize, tiles: Vec<u8>) -> Result<Self> {
+        if tiles.len() != width * height {
+            bail!("map is {width}x{height} but has {} tiles", tiles.len());
+        }
+        let doors = tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, &tile)| tile == DOOR_TILE)
+            .map(|(i, _)| ((i % width, i / width), Door::default()))
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            tiles,
+            tile_heights: HashMap::new(),
+            doors,
+            spawn_pos: Vector2::new(width as f32 / 2., height as f32 / 2.),
+            spawn_dir: Vector2::new(-1., 0.),
+            lights: Vec::new(),
+            see_through: HashSet::from([WINDOW_TILE]),
+            tile_colors: HashMap::new(),
+            sprites: Vec::new(),
+            animations: HashMap::new(),
+            world_scale: 1.,
+            wrap: false,
+            floors: None,
+            liquids: HashMap::new(),
+            time: 0.,
+            diagonals: HashMap::new(),
+            faces: HashMap::new(),
+            revision: next_revision(),
+            lights_revision: next_revision(),
+        })
+    }
+
+    /// Parses a map with one line per row and one digit per tile.
+    ///
+    /// Lines starting with a letter are directives rather than rows:
+    /// - `height <tile> <multiplier>` sets the wall height of a tile id.
+    /// - `spawn <x> <y> <dir x> <dir y>` sets where the player starts and which way they face.
+    /// - `light <x> <y> <radius> <rrggbb>` adds a point light with a hex color.
+    /// - `transparent <tile>` lets rays see through a tile id.
+    /// - `color <tile> <rrggbb[aa]>` sets the flat color of a tile id, drawn when it has no
+    ///   texture. Alpha is only used by see-through tiles.
+    /// - `sprite <x> <y> <texture>` places a sprite using the renderer's sprite texture index.
+    /// - `animate <tile> <seconds> <frame> [frame...]` draws a tile id with each frame tile's
+    ///   texture in turn, holding each for the given time.
+    /// - `scale <units>` sets how many world units wide each tile is.
+    /// - `wrap` joins the map's opposite edges, so it repeats endlessly.
+    /// - `floor <x> <y> <width> <height> <tile>` sets the floor tile id over a rectangle.
+    /// - `liquid <tile> <rrggbb> <ripple> <speed> <scale>` draws a floor tile id as a
+    ///   rippling color.
+    /// - `diagonal <tile> <corner>` makes a tile id a diagonal wall filling the named
+    ///   corner of its cell: `top-left`, `top-right`, `bottom-left` or `bottom-right`.
+    /// - `faces <tile> <north> <south> <east> <west>` draws each face of a tile id with
+    ///   another tile id's texture or color.
+    pub fn parse(source: &str) -> Result<Self> {
+        let (directives, rows): (Vec<&str>, Vec<&str>) = source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .partition(|line| line.starts_with(|c: char| c.is_ascii_alphabetic()));
+        // Widths are in tiles, which are characters rather than bytes
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        if width == 0 {
+            bail!("map is empty");
+        }
+
+        let mut tiles = Vec::with_capacity(width * rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            let row_width = row.chars().count();
+            if row_width != width {
+                bail!("row {y} is {row_width} tiles wide, expected {width}");
+            }
+            for (x, c) in row.chars().enumerate() {
+                let tile = c
+                    .to_digit(10)
+                    .with_context(|| format!("invalid tile {c:?} at ({x}, {y})"))?;
+                tiles.push(tile as u8);
+            }
+        }
+        let mut map = Self::new(width, rows.len(), tiles)?;
+
+        for directive in directives {
+            let words: Vec<&str> = directive.split_whitespace().collect();
+            match words[..] {
+                ["height", tile, height] => {
+                    let tile = tile
+                        .parse()
+                        .with_context(|| format!("invalid tile id in {directive:?}"))?;
+                    let height = height
+                        .parse()
+                        .with_context(|| format!("invalid height in {directive:?}"))?;
+                    map.set_tile_height(tile, height);
+                }
+                ["spawn", x, y, dir_x, dir_y] => {
+                    let coord = |word: &str| {
+                        word.parse::<f32>()
+                            .with_context(|| format!("invalid coordinate in {directive:?}"))
+                    };
+                    map.set_spawn(
+                        Vector2::new(coord(x)?, coord(y)?),
+                        Vector2::new(coord(dir_x)?, coord(dir_y)?),
+                    )
+                    .with_context(|| format!("invalid spawn in {directive:?}"))?;
+                }
+                ["transparent", tile] => {
+                    let tile = tile
+                        .parse()
+                        .with_context(|| format!("invalid tile id in {directive:?}"))?;
+                    map.set_opaque(tile, false);
+                }
+                ["light", x, y, radius, color] => {
+                    let number = |word: &str| {
+                        word.parse::<f32>()
+                            .with_context(|| format!("invalid number in {directive:?}"))
+                    };
+                    let [r, g, b, _] = parse_color(color)
+                        .filter(|_| color.len() == 6)
+                        .with_context(|| format!("invalid color in {directive:?}"))?;
+                    let channel = |value: u8| value as f32 / 255.;
+                    map.add_light(Light {
+                        position: Vector2::new(number(x)?, number(y)?),
+                        color: [channel(r), channel(g), channel(b)],
+                        radius: number(radius)?,
+                    });
+                }
+                ["color", tile, color] => {
+                    let tile = tile
+                        .parse()
+                        .with_context(|| format!("invalid tile id in {directive:?}"))?;
+                    let rgba = parse_color(color)
+                        .with_context(|| format!("invalid color in {directive:?}"))?;
+                    map.set_tile_color(tile, u32::from_le_bytes(rgba));
+                }
+                ["sprite", x, y, texture] => {
+                    let coord = |word: &str| {
+                        word.parse::<f32>()
+                            .with_context(|| format!("invalid coordinate in {directive:?}"))
+                    };
+                    map.add_sprite(Sprite {
+                        position: Vector2::new(coord(x)?, coord(y)?),
+                        texture: texture
+                            .parse()
+                            .with_context(|| format!("invalid texture in {directive:?}"))?,
+                    });
+                }
+                ["animate", tile, frame_time, ref frames @ ..] if !frames.is_empty() => {
+                    let tile_id = |word: &str| {
+                        word.parse::<u8>()
+                            .with_context(|| format!("invalid tile id in {directive:?}"))
+                    };
+                    let frame_time = frame_time
+                        .parse()
+                        .with_context(|| format!("invalid frame time in {directive:?}"))?;
+                    let frames = frames
+                        .iter()
+                        .map(|&frame| tile_id(frame))
+                        .collect::<Result<_>>()?;
+                    let animation = Animation::new(frames, frame_time)
+                        .with_context(|| format!("invalid animation {directive:?}"))?;
+                    map.set_animation(tile_id(tile)?, animation);
+                }
+                ["scale", scale] => {
+                    let scale = scale
+                        .parse()
+                        .with_context(|| format!("invalid scale in {directive:?}"))?;
+                    map.set_world_scale(scale)
+                        .with_context(|| format!("invalid scale in {directive:?}"))?;
+                }
+                ["wrap"] => map.set_wrap(true),
+                ["floor", x, y, width, height, tile] => {
+                    let number = |word: &str| {
+                        word.parse::<usize>()
+                            .with_context(|| format!("invalid number in {directive:?}"))
+                    };
+                    let tile = tile
+                        .parse()
+                        .with_context(|| format!("invalid tile id in {directive:?}"))?;
+                    let (x, y) = (number(x)?, number(y)?);
+                    let end = |start: usize, size: &str| {
+                        start
+                            .checked_add(number(size)?)
+                            .with_context(|| format!("invalid floor {directive:?}"))
+                    };
+                    let (x_end, y_end) = (end(x, width)?, end(y, height)?);
+                    for y in y..y_end {
+                        for x in x..x_end {
+                            map.set_floor_tile(x, y, tile)
+                                .with_context(|| format!("invalid floor {directive:?}"))?;
+                        }
+                    }
+                }
+                ["liquid", tile, color, ripple, speed, scale] => {
+                    let number = |word: &str| {
+                        word.parse::<f32>()
+                            .with_context(|| format!("invalid number in {directive:?}"))
+                    };
+                    let tile = tile
+                        .parse()
+                        .with_context(|| format!("invalid tile id in {directive:?}"))?;
+                    let rgba = parse_color(color)
+                        .with_context(|| format!("invalid color in {directive:?}"))?;
+                    map.set_liquid(
+                        tile,
+                        Liquid {
+                            color: u32::from_le_bytes(rgba),
+                            ripple: number(ripple)?,
+                            speed: number(speed)?,
+                            scale: number(scale)?,
+                        },
+                    );
+                }
+                ["diagonal", tile, corner] => {
+                    let tile = tile
+                        .parse()
+                        .with_context(|| format!("invalid tile id in {directive:?}"))?;
+                    let diagonal = Diagonal::parse(corner)
+                        .with_context(|| format!("invalid corner in {directive:?}"))?;
+                    map.set_diagonal(tile, diagonal);
+                }
+                ["faces", tile, north, south, east, west] => {
+                    let id = |word: &str| {
+                        word.parse::<u8>()
+                            .with_context(|| format!("invalid tile id in {directive:?}"))
+                    };
+                    map.set_faces(id(tile)?, [id(north)?, id(south)?, id(east)?, id(west)?]);
+                }
+                _ => bail!("unrecognised directive {directive:?}"),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Parses a JSON level: an object holding the same things a text map can, with tile
+    /// ids free to go past 9. Only `tiles` is required.
+    /// - `tiles`: an array of rows, each an array of tile ids.
+    /// - `spawn`: `{"position": [x, y], "direction": [x, y]}`.
+    /// - `palette`: tile ids to `"rrggbb[aa]"` colors, as in `{"1": "c0c0c0"}`.
+    /// - `heights`: tile ids to wall height multipliers.
+    /// - `transparent`: an array of tile ids rays see through.
+    /// - `lights`: an array of `{"position": [x, y], "radius": r, "color": "rrggbb"}`.
+    /// - `sprites`: an array of `{"position": [x, y], "texture": index}`.
+    /// - `animations`: tile ids to `{"frames": [tile, ...], "frame_time": seconds}`.
+    /// - `scale`: world units per tile.
+    /// - `wrap`: true to join the map's opposite edges.
+    /// - `floors`: floor tile ids, an array of rows the same size as `tiles`.
+    /// - `liquids`: floor tile ids to `{"color": "rrggbb", "ripple": r, "speed": s,
+    ///   "scale": k}`. Only the color is required; the rest default to 0.
+    /// - `diagonals`: tile ids to the corner they fill, as in `{"6": "top-left"}`.
+    /// - `faces`: tile ids to the tile ids drawn on their north, south, east and west faces,
+    ///   as in `{"1": [1, 1, 4, 4]}`.
+    pub fn parse_json(source: &str) -> Result<Self> {
+        let level = Value::parse(source)?;
+        if level.as_object().is_none() {
+            bail!("a level should be an object");
+        }
+        let number = |value: &Value, what: &str| {
+            value
+                .as_f64()
+                .map(|number| number as f32)
+                .with_context(|| format!("{what} should be a number"))
+        };
+        let integer = |value: &Value, what: &str| {
+            value
+                .as_f64()
+                .filter(|number| number.fract() == 0. && *number >= 0.)
+                .map(|number| number as u64)
+                .with_context(|| format!("{what} should be a whole number"))
+        };
+        let tile = |value: &Value| -> Result<u8> {
+            let id = integer(value, "a tile id")?;
+            u8::try_from(id)
+                .ok()
+                .with_context(|| format!("tile id {id} is over 255"))
+        };
+        let tile_key = |key: &str| {
+            key.parse::<u8>()
+                .with_context(|| format!("invalid tile id {key:?}"))
+        };
+        let vector = |value: Option<&Value>, what: &str| match value.and_then(Value::as_array) {
+            Some([x, y]) => Ok(Vector2::new(number(x, what)?, number(y, what)?)),
+            _ => bail!("{what} should be an [x, y] pair"),
+        };
+        let color = |value: &Value| {
+            value
+                .as_str()
+                .and_then(parse_color)
+                .with_context(|| match value.as_str() {
+                    Some(color) => format!("invalid color {color:?}"),
+                    None => "colors should be hex strings".to_string(),
+                })
+        };
+        let entries = |key: &str| -> Result<&[(String, Value)]> {
+            match level.get(key) {
+                None => Ok(&[]),
+                Some(value) => value
+                    .as_object()
+                    .with_context(|| format!("{key} should be an object")),
+            }
+        };
+        let items = |key: &str| -> Result<&[Value]> {
+            match level.get(key) {
+                None => Ok(&[]),
+                Some(value) => value
+                    .as_array()
+                    .with_context(|| format!("{key} should be an array")),
+            }
+        };
+
+        let rows = level
+            .get("tiles")
+            .and_then(Value::as_array)
+            .context("a level needs a tiles array")?;
+        let width = rows.first().and_then(Value::as_array).map_or(0, <[_]>::len);
+        if width == 0 {
+            bail!("map is empty");
+        }
+        let mut tiles = Vec::with_capacity(width * rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            let row = row
+                .as_array()
+                .with_context(|| format!("row {y} should be an array"))?;
+            if row.len() != width {
+                bail!("row {y} is {} tiles wide, expected {width}", row.len());
+            }
+            for (x, id) in row.iter().enumerate() {
+                tiles.push(tile(id).with_context(|| format!("invalid tile at ({x}, {y})"))?);
+            }
+        }
+        let mut map = Self::new(width, rows.len(), tiles)?;
+
+        if let Some(spawn) = level.get("spawn") {
+            map.set_spawn(
+                vector(spawn.get("position"), "spawn position")?,
+                vector(spawn.get("direction"), "spawn direction")?,
+            )?;
+        }
+        for (key, value) in entries("palette")? {
+            map.set_tile_color(tile_key(key)?, u32::from_le_bytes(color(value)?));
+        }
+        for (key, value) in entries("heights")? {
+            map.set_tile_height(tile_key(key)?, number(value, "a height")?);
+        }
+        for id in items("transparent")? {
+            map.set_opaque(tile(id)?, false);
+        }
+        for light in items("lights")? {
+            let [r, g, b, _] = color(light.get("color").unwrap_or(&Value::Null))?;
+            let channel = |value: u8| value as f32 / 255.;
+            map.add_light(Light {
+                position: vector(light.get("position"), "light position")?,
+                color: [channel(r), channel(g), channel(b)],
+                radius: number(light.get("radius").unwrap_or(&Value::Null), "light radius")?,
+            });
+        }
+        for sprite in items("sprites")? {
+            let texture = sprite.get("texture").unwrap_or(&Value::Null);
+            map.add_sprite(Sprite {
+                position: vector(sprite.get("position"), "sprite position")?,
+                texture: integer(texture, "sprite texture")? as usize,
+            });
+        }
+        for (key, value) in entries("animations")? {
+            let frames = value
+                .get("frames")
+                .and_then(Value::as_array)
+                .context("animation frames should be an array")?
+                .iter()
+                .map(tile)
+                .collect::<Result<_>>()?;
+            let frame_time = number(
+                value.get("frame_time").unwrap_or(&Value::Null),
+                "animation frame time",
+            )?;
+            map.set_animation(tile_key(key)?, Animation::new(frames, frame_time)?);
+        }
+        if let Some(scale) = level.get("scale") {
+            map.set_world_scale(number(scale, "scale")?)?;
+        }
+        if let Some(rows) = level.get("floors") {
+            let rows = rows.as_array().context("floors should be an array")?;
+            if rows.len() != map.height {
+                bail!("floors has {} rows, expected {}", rows.len(), map.height);
+            }
+            for (y, row) in rows.iter().enumerate() {
+                let row = row
+                    .as_array()
+                    .with_context(|| format!("floor row {y} should be an array"))?;
+                if row.len() != map.width {
+                    bail!(
+                        "floor row {y} is {} tiles wide, expected {}",
+                        row.len(),
+                        map.width
+                    );
+                }
+                for (x, id) in row.iter().enumerate() {
+                    let id = tile(id).with_context(|| format!("invalid floor at ({x}, {y})"))?;
+                    map.set_floor_tile(x, y, id)?;
+                }
+            }
+        }
+        for (key, value) in entries("liquids")? {
+            let optional = |name: &str| match value.get(name) {
+                Some(number_value) => number(number_value, name),
+                None => Ok(0.),
+            };
+            map.set_liquid(
+                tile_key(key)?,
+                Liquid {
+                    color: u32::from_le_bytes(color(value.get("color").unwrap_or(&Value::Null))?),
+                    ripple: optional("ripple")?,
+                    speed: optional("speed")?,
+                    scale: optional("scale")?,
+                },
+            );
+        }
+        for (key, value) in entries("diagonals")? {
+            let diagonal = value
+                .as_str()
+                .and_then(Diagonal::parse)
+                .with_context(|| format!("invalid corner for diagonal tile {key}"))?;
+            map.set_diagonal(tile_key(key)?, diagonal);
+        }
+        for (key, value) in entries("faces")? {
+            let faces = value
+                .as_array()
+                .filter(|faces| faces.len() == 4)
+                .with_context(|| format!("faces of tile {key} should be an array of 4"))?;
+            let faces = [&faces[0], &faces[1], &faces[2], &faces[3]].map(tile);
+            let [north, south, east, west] = faces;
+            map.set_faces(tile_key(key)?, [north?, south?, east?, west?]);
+        }
+        if let Some(wrap) = level.get("wrap") {
+            map.set_wrap(wrap.as_bool().context("wrap should be true or false")?);
+        }
+        Ok(map)
+    }
+
+    /// Loads a map, as JSON if the file ends in `.json` and as text otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read map {}", path.display()))?;
+        let map = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            Self::parse_json(&source)
+        } else {
+            Self::parse(&source)
+        };
+        map.with_context(|| format!("failed to parse map {}", path.display()))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn world_scale(&self) -> f32 {
+        self.world_scale
+    }
+
+    /// Sets how many world units wide each tile is. Fails unless it's positive and finite.
+    pub fn set_world_scale(&mut self, scale: f32) -> Result<()> {
+        if !scale.is_finite() || scale <= 0. {
+            bail!("world scale {scale} should be positive");
+        }
+        self.world_scale = scale;
+        self.revision = next_revision();
+        Ok(())
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+        self.revision = next_revision();
+    }
+
+    /// Identifies the shape of the map as rays see it: walls, doors and how far they're open,
+    /// diagonals, see-through tiles, wrapping and scale. It changes whenever any of those
+    /// do, so two equal revisions always cast the same rays. Textures and lights aren't
+    /// counted.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// The wall height multiplier for a tile id. Rays still stop at any solid tile;
+    /// this only changes how tall the wall is drawn.
+    pub fn tile_height(&self, tile: u8) -> f32 {
+        self.tile_heights.get(&tile).copied().unwrap_or(1.)
+    }
+
+    pub fn set_tile_height(&mut self, tile: u8, height: f32) {
+        self.tile_heights.insert(tile, height);
+    }
+
+    /// Where the player starts on this map.
+    pub fn spawn_pos(&self) -> Vector2<f32> {
+        self.spawn_pos
+    }
+
+    /// Which way the player faces on spawning. Not necessarily normalized.
+    pub fn spawn_dir(&self) -> Vector2<f32> {
+        self.spawn_dir
+    }
+
+    /// Fails for a position that isn't finite, or a direction that isn't or has no length,
+    /// since a camera can't look along it.
+    pub fn set_spawn(&mut self, pos: Vector2<f32>, dir: Vector2<f32>) -> Result<()> {
+        if !pos.x.is_finite() || !pos.y.is_finite() {
+            bail!("spawn position ({}, {}) isn't finite", pos.x, pos.y);
+        }
+        if !dir.x.is_finite() || !dir.y.is_finite() {
+            bail!("spawn direction ({}, {}) isn't finite", dir.x, dir.y);
+        }
+        if dir.x == 0. && dir.y == 0. {
+            bail!("spawn direction has no length");
+        }
+        self.spawn_pos = pos;
+        self.spawn_dir = dir;
+        Ok(())
+    }
+
+    /// The nearest position to `pos` that lies in a tile on the map, or on a wrapping map the
+    /// same position brought back onto it. A position that isn't finite has no nearest one,
+    /// so it's sent back to the spawn point.
+    pub fn clamp_position(&self, pos: Vector2<f32>) -> Vector2<f32> {
+        if !pos.x.is_finite() || !pos.y.is_finite() {
+            return self.spawn_pos;
+        }
+        if self.wrap {
+            return self.wrap_position(pos);
+        }
+        Vector2::new(
+            pos.x.clamp(EDGE_MARGIN, self.width as f32 - EDGE_MARGIN),
+            pos.y.clamp(EDGE_MARGIN, self.height as f32 - EDGE_MARGIN),
+        )
+    }
+
+    /// On a wrapping map, the position on the map that `pos` lies over, counting the map as
+    /// repeating endlessly. Other maps don't repeat, so it's returned as it is.
+    pub fn wrap_position(&self, pos: Vector2<f32>) -> Vector2<f32> {
+        if !self.wrap {
+            return pos;
+        }
+        // A tiny negative coordinate wraps to the size itself once rounded, which would floor
+        // to a tile past the far edge
+        let wrap = |value: f32, size: usize| {
+            let wrapped = value.rem_euclid(size as f32);
+            if wrapped < size as f32 {
+                wrapped
+            } else {
+                0.
+            }
+        };
+        Vector2::new(wrap(pos.x, self.width), wrap(pos.y, self.height))
+    }
+
+    /// A DDA walking a ray from `origin` along `dir` over this map's grid, wrapping at the
+    /// edges if the map does.
+    pub fn dda(&self, origin: Vector2<f32>, dir: Vector2<f32>) -> Dda {
+        let dda = Dda::new(origin, dir);
+        if self.wrap {
+            dda.wrapping(self.width, self.height)
+        } else {
+            dda
+        }
+    }
+
+    /// Whether rays stop at a tile id. Empty space counts as opaque; it's never drawn.
+    pub fn is_opaque(&self, tile: u8) -> bool {
+        !self.see_through.contains(&tile)
+    }
+
+    pub fn set_opaque(&mut self, tile: u8, opaque: bool) {
+        if opaque {
+            self.see_through.remove(&tile);
+        } else {
+            self.see_through.insert(tile);
+        }
+        self.revision = next_revision();
+    }
+
+    pub fn tile_colors(&self) -> &HashMap<u8, u32> {
+        &self.tile_colors
+    }
+
+    pub fn set_tile_color(&mut self, tile: u8, color: u32) {
+        self.tile_colors.insert(tile, color);
+    }
+
+    pub fn sprites(&self) -> &[Sprite] {
+        &self.sprites
+    }
+
+    pub fn add_sprite(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn set_animation(&mut self, tile: u8, animation: Animation) {
+        self.animations.insert(tile, animation);
+    }
+
+    /// The tile id whose texture a tile is drawn with right now: its animation's current
+    /// frame, or the tile itself if it isn't animated.
+    pub fn texture_tile(&self, tile: u8) -> u8 {
+        self.animations.get(&tile).map_or(tile, Animation::frame)
+    }
+
+    /// Moves every animation, and the liquids, on by `dt` seconds.
+    pub fn update_animations(&mut self, dt: f32) {
+        for animation in self.animations.values_mut() {
+            animation.advance(dt);
+        }
+        self.time += dt;
+    }
+
+    /// Seconds of animation so far, for drawing liquids.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// The floor tile id at (x, y): 0 for plain floor, and for anywhere off the map.
+    pub fn floor_tile(&self, x: usize, y: usize) -> u8 {
+        match &self.floors {
+            Some(floors) if x < self.width && y < self.height => floors[y * self.width + x],
+            _ => 0,
+        }
+    }
+
+    /// Sets the floor tile id at (x, y). Fails if the cell is off the map.
+    pub fn set_floor_tile(&mut self, x: usize, y: usize, tile: u8) -> Result<()> {
+        if x >= self.width || y >= self.height {
+            bail!("floor tile ({x}, {y}) is off the map");
+        }
+        let floors = self
+            .floors
+            .get_or_insert_with(|| vec![0; self.width * self.height]);
+        floors[y * self.width + x] = tile;
+        Ok(())
+    }
+
+    pub fn set_liquid(&mut self, tile: u8, liquid: Liquid) {
+        self.liquids.insert(tile, liquid);
+    }
+
+    /// Whether any floor tile is drawn as a liquid. When none is, the floor can be drawn
+    /// without looking up tiles at all.
+    pub fn has_liquids(&self) -> bool {
+        self.floors.is_some() && !self.liquids.is_empty()
+    }
+
+    /// Whether anything drawn changes with `time`: animated tiles or liquids.
+    pub fn is_animated(&self) -> bool {
+        !self.animations.is_empty() || self.has_liquids()
+    }
+
+    /// The liquid covering the floor at a world position, if any.
+    pub fn liquid_at(&self, pos: Vector2<f32>) -> Option<&Liquid> {
+        let pos = self.wrap_position(pos);
+        if pos.x < 0. || pos.y < 0. {
+            return None;
+        }
+        match self.floor_tile(pos.x as usize, pos.y as usize) {
+            0 => None,
+            tile => self.liquids.get(&tile),
+        }
+    }
+
+    /// Makes `tile` a diagonal wall filling the given corner of its cell.
+    pub fn set_diagonal(&mut self, tile: u8, diagonal: Diagonal) {
+        self.diagonals.insert(tile, diagonal);
+        self.revision = next_revision();
+    }
+
+    /// Draws each face of `tile` as another tile id, given in north, south, east, west order.
+    /// Tiles without faces set look the same from every side.
+    pub fn set_faces(&mut self, tile: u8, faces: [u8; 4]) {
+        self.faces.insert(tile, faces);
+    }
+
+    /// The tile id drawn on `face` of `tile`: the one set for it, or the tile itself.
+    pub fn face_tile(&self, tile: u8, face: Option<Face>) -> u8 {
+        let Some((faces, face)) = self.faces.get(&tile).zip(face) else {
+            return tile;
+        };
+        match face {
+            Face::North => faces[0],
+            Face::South => faces[1],
+            Face::East => faces[2],
+            Face::West => faces[3],
+        }
+    }
+
+    /// The diagonal wall at (x, y), if that's what the cell holds.
+    pub fn diagonal(&self, x: usize, y: usize) -> Option<Diagonal> {
+        self.diagonals.get(&self.get(x, y)?).copied()
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+        self.lights_revision = next_revision();
+    }
+
+    /// Identifies the map's lights, like `revision` does its shape, so a `LightMap` built
+    /// from them is only rebuilt when they change.
+    pub fn lights_revision(&self) -> u64 {
+        self.lights_revision
+    }
+
+    /// All tiles, row-major.
+    pub fn tiles(&self) -> &[u8] {
+        &self.tiles
+    }
+
+    /// The tile at (x, y), or None if the cell lies outside the map.
+    pub fn get(&self, x: usize, y: usize) -> Option<u8> {
+        if x < self.width && y < self.height {
+            Some(self.tiles[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Whether the world position lies in a solid tile. Anything off the map counts as solid,
+    /// unless the map wraps, doors count until they're nearly open, and diagonal walls only
+    /// in their filled half.
+    pub fn is_wall(&self, x: f32, y: f32) -> bool {
+        let pos = self.wrap_position(Vector2::new(x, y));
+        if pos.x < 0. || pos.y < 0. {
+            return true;
+        }
+        let (x, y) = (pos.x as usize, pos.y as usize);
+        if let Some(diagonal) = self.diagonal(x, y) {
+            return diagonal.is_solid(pos - Vector2::new(x as f32, y as f32));
+        }
+        match self.door(x, y) {
+            Some(door) => door.open < DOOR_PASSABLE,
+            None => self.get(x, y) != Some(0),
+        }
+    }
+
+    /// Casts a ray from `origin` along `dir` and returns the first tile that blocks sight and
+    /// how far along the ray it starts, in multiples of `dir`, or None if the ray leaves the
+    /// map first. The origin's own tile is skipped. Empty, see-through and passable door
+    /// tiles don't block, and every other tile blocks its whole cell, thin and diagonal
+    /// walls included.
+    pub fn cast(&self, origin: Vector2<f32>, dir: Vector2<f32>) -> Option<(u8, f32)> {
+        let origin = self.wrap_position(origin);
+        if origin.x < 0. || origin.y < 0. {
+            return None;
+        }
+        self.dda(origin, dir)
+            .map_while(|step| Some((step, self.get(step.x, step.y)?)))
+            .find(|&(step, tile)| {
+                let passable = match self.door(step.x, step.y) {
+                    Some(door) => door.open >= DOOR_PASSABLE,
+                    None => tile == 0 || !self.is_opaque(tile),
+                };
+                !passable
+            })
+            .map(|(step, tile)| (tile, step.dist))
+    }
+
+    /// Whether nothing blocks sight on the straight line from `from` to `to`, as `cast`
+    /// decides. A blocking tile counts only if the line enters it before reaching `to`.
+    pub fn line_of_sight(&self, from: Vector2<f32>, to: Vector2<f32>) -> bool {
+        let dir = to - from;
+        if dir.x == 0. && dir.y == 0. {
+            return true;
+        }
+        self.cast(from, dir).is_none_or(|(_, dist)| dist >= 1.)
+    }
+
+    /// If (x, y) holds a thin wall, how far it has slid aside: 0 for a fixed panel, and the
+    /// door's opening for a door.
+    pub fn thin_wall(&self, x: usize, y: usize) -> Option<f32> {
+        match self.get(x, y)? {
+            THIN_WALL_TILE => Some(0.),
+            _ => self.door(x, y).map(|door| door.open),
+        }
+    }
+
+    /// Which way the thin wall at (x, y) runs, as a `RayHit::side`: 0 for a plane of constant
+    /// x when more of its neighbouring walls are above and below it than to either side, and
+    /// 1 for one of constant y otherwise. It's fixed by the map, so a door stays put
+    /// whichever way it's seen from.
+    pub fn thin_wall_side(&self, x: usize, y: usize) -> u8 {
+        let walls = |cells: [(Option<usize>, Option<usize>); 2]| {
+            cells
+                .into_iter()
+                .filter_map(|(x, y)| self.get(x?, y?))
+                .filter(|&tile| tile != 0)
+                .count()
+        };
+        let beside = walls([(x.checked_sub(1), Some(y)), (x.checked_add(1), Some(y))]);
+        let above_below = walls([(Some(x), y.checked_sub(1)), (Some(x), y.checked_add(1))]);
+        (above_below <= beside) as u8
+    }
+
+    pub fn door(&self, x: usize, y: usize) -> Option<Door> {
+        self.doors.get(&(x, y)).copied()
+    }
+
+    /// Every door and its cell, in no particular order.
+    pub fn doors(&self) -> impl Iterator<Item = ((usize, usize), Door)> + '_ {
+        self.doors.iter().map(|(&cell, &door)| (cell, door))
+    }
+
+    /// Replaces the state of the door at (x, y). Returns false if there is no door there.
+    pub fn set_door(&mut self, x: usize, y: usize, door: Door) -> bool {
+        match self.doors.get_mut(&(x, y)) {
+            Some(existing) => {
+                *existing = door;
+                self.revision = next_revision();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Starts the door at (x, y) opening if it's closed or closing, or closing if it's open.
+    /// Returns false if there is no door there.
+    pub fn toggle_door(&mut self, x: usize, y: usize) -> bool {
+        match self.doors.get_mut(&(x, y)) {
+            Some(door) => {
+                door.opening = !door.opening;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Slides every moving door by `step`. A door won't close on the occupied cell.
+    pub fn update_doors(&mut self, step: f32, occupied: (usize, usize)) {
+        let mut moved = false;
+        for (&cell, door) in &mut self.doors {
+            let open = door.open;
+            if door.opening {
+                door.open = (door.open + step).min(1.);
+            } else if cell != occupied {
+                door.open = (door.open - step).max(0.);
+            }
+            moved |= door.open != open;
+        }
+        if moved {
+            self.revision = next_revision();
+        }
+    }
+}
+
+/// Parses an `rrggbb` or `rrggbbaa` hex color into RGBA bytes. Alpha defaults to opaque.
+fn parse_color(hex: &str) -> Option<[u8; 4]> {
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    let alpha = if hex.len() == 8 { channel(6)? } else { 0xFF };
+    Some([channel(0)?, channel(2)?, channel(4)?, alpha])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_measured_in_tiles() {
+        // A multi-byte character is one bad tile, not a row too wide
+        let err = Map::parse("111\n1é1\n111").err().unwrap();
+        assert_eq!(format!("{err:#}"), "invalid tile 'é' at (1, 1)");
+        let err = Map::parse("111\n1é\n111").err().unwrap();
+        assert_eq!(format!("{err:#}"), "row 1 is 2 tiles wide, expected 3");
+    }
+
+    #[test]
+    fn spawns_that_cant_be_looked_along_are_errors() {
+        for spawn in ["0 0", "NaN 1", "inf 0"] {
+            let err = Map::parse(&format!("spawn 1.5 1.5 {spawn}\n111\n101\n111"))
+                .err()
+                .unwrap();
+            let err = format!("{err:#}");
+            assert!(err.starts_with("invalid spawn"), "{spawn}: {err}");
+        }
+        let err = Map::parse("spawn inf 1.5 1 0\n111\n101\n111")
+            .err()
+            .unwrap();
+        assert!(format!("{err:#}").contains("isn't finite"), "{err:#}");
+
+        for direction in ["[0, 0]", "[1e999, 0]"] {
+            let level = format!(
+                r#"{{"tiles": [[1, 1, 1], [1, 0, 1], [1, 1, 1]],
+                    "spawn": {{"position": [1.5, 1.5], "direction": {direction}}}}}"#
+            );
+            let err = Map::parse_json(&level).err().unwrap();
+            assert!(format!("{err:#}").contains("spawn direction"), "{err:#}");
+        }
+        assert!(Map::parse("spawn 1.5 1.5 0 -2\n111\n101\n111").is_ok());
+    }
+
+    #[test]
+    fn floors_off_the_map_are_errors() {
+        for floor in [
+            "1 1 18446744073709551615 1 2",
+            "18446744073709551615 1 1 1 2",
+            "1 1 3 1 2",
+        ] {
+            let err = Map::parse(&format!("floor {floor}\n111\n101\n111"))
+                .err()
+                .unwrap();
+            assert!(format!("{err:#}").starts_with("invalid floor"), "{err:#}");
+        }
+        let map = Map::parse("floor 1 1 1 1 2\n111\n101\n111").unwrap();
+        assert_eq!(map.floor_tile(1, 1), 2);
+    }
+
+    #[test]
+    fn large_map_is_walled_in_at_its_size() {
+        let map = Map::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/maps/large.map"
+        ))
+        .unwrap();
+        assert_eq!((map.width(), map.height()), (32, 24));
+        for x in 0..32 {
+            assert_eq!((map.get(x, 0), map.get(x, 23)), (Some(1), Some(1)));
+        }
+        for y in 0..24 {
+            assert_eq!((map.get(0, y), map.get(31, y)), (Some(1), Some(1)));
+        }
+        assert_eq!(map.get(32, 0), None);
+        assert_eq!(map.get(0, 24), None);
+        assert_eq!(map.get(20, 17), Some(DOOR_TILE));
+        assert_eq!(map.get(17, 8), Some(3));
+    }
+
+    #[test]
+    fn line_of_sight_on_the_sample_map() {
+        let map = Map::default();
+        let point = |x, y| Vector2::new(x, y);
+        // Open floor, including right up to a wall
+        let visible = [
+            (point(2.5, 2.5), point(12.5, 2.5)),
+            (point(2.5, 12.5), point(12.5, 12.5)),
+            (point(1.5, 1.5), point(13.5, 7.5)),
+            (point(2.5, 9.5), point(3.9, 9.5)),
+        ];
+        // Behind the pillar of 2s at (4, 8) and (4, 9), and the row of 3s along y = 9
+        let occluded = [
+            (point(2.5, 9.5), point(6.5, 9.5)),
+            (point(3.5, 7.5), point(5.5, 10.5)),
+            (point(8.5, 7.5), point(8.5, 11.5)),
+            (point(2.5, 2.5), point(12.5, 12.5)),
+        ];
+        for (from, to) in visible {
+            assert!(map.line_of_sight(from, to), "{from:?} to {to:?}");
+            assert!(map.line_of_sight(to, from), "{to:?} to {from:?}");
+        }
+        for (from, to) in occluded {
+            assert!(!map.line_of_sight(from, to), "{from:?} to {to:?}");
+            assert!(!map.line_of_sight(to, from), "{to:?} to {from:?}");
+        }
+        assert_eq!(map.cast(point(2.5, 9.5), point(1., 0.)), Some((2, 1.5)));
+        assert_eq!(map.cast(point(8.5, 7.5), point(0., 2.)), Some((3, 0.75)));
+    }
+}
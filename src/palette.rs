@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use crate::map::{Map, DOOR_TILE, THIN_WALL_TILE, WINDOW_TILE};
+
+/// Drawn for tile ids with no color of their own, loud enough to stand out.
+const MISSING_COLOR: u32 = 0xFFFF00FF;
+const DEFAULT_COLORS: [(u8, u32); 6] = [
+    (1, 0xFF0000FF),
+    (2, 0xFF00FF00),
+    (3, 0xFFFF0000),
+    (DOOR_TILE, 0xFF2080C0),
+    (THIN_WALL_TILE, 0xFF608060),
+    (WINDOW_TILE, 0x60FFC080),
+];
+
+/// The flat color of each tile id, in the pixel buffer's 0xAABBGGRR format. Used for
+/// untextured walls and the minimap.
+#[derive(Clone, PartialEq)]
+pub struct Palette {
+    colors: HashMap<u8, u32>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            colors: DEFAULT_COLORS.into_iter().collect(),
+        }
+    }
+}
+
+impl Palette {
+    /// The default colors, overridden by any the map sets.
+    pub fn for_map(map: &Map) -> Self {
+        let mut palette = Self::default();
+        palette.colors.extend(map.tile_colors());
+        palette
+    }
+
+    pub fn get(&self, tile: u8) -> u32 {
+        self.colors.get(&tile).copied().unwrap_or(MISSING_COLOR)
+    }
+
+    pub fn set(&mut self, tile: u8, color: u32) {
+        self.colors.insert(tile, color);
+    }
+
+    /// Every tile id's color, indexed by id, for handing to shaders.
+    pub fn table(&self) -> [u32; 256] {
+        std::array::from_fn(|tile| self.get(tile as u8))
+    }
+}
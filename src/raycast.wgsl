@@ -0,0 +1,143 @@
+// Compute raycaster: one invocation per screen column, mirroring Renderer's untextured path
+
+struct Uniforms {
+    player_pos: vec2<f32>,
+    facing_dir: vec2<f32>,
+    view_plane: vec2<f32>,
+    map_size: vec2<u32>,
+    screen_size: vec2<u32>,
+    projection: f32,
+    horizon: f32,
+    floor_color: vec4<f32>,
+    ceiling_color: vec4<f32>,
+    eye_height: f32,
+    wrap: u32,
+    max_dist: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> u: Uniforms;
+@group(0) @binding(1)
+var<storage, read> tiles: array<u32>;
+@group(0) @binding(2)
+var output: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(3)
+var<storage, read> palette: array<u32, 256>;
+
+// Must match FOG_FALLOFF, FOG_MIN_BRIGHTNESS and FACE_BRIGHTNESS in lighting.rs
+const FOG_FALLOFF: f32 = 0.16;
+const FOG_MIN_BRIGHTNESS: f32 = 0.2;
+const Y_FACE_BRIGHTNESS: f32 = 0.75;
+// Must match VIEW_FADE in lighting.rs
+const VIEW_FADE: f32 = 0.25;
+// Must match WRAP_MAX_STEPS in dda.rs
+const WRAP_MAX_STEPS: u32 = 256u;
+
+// Palette entries are packed 0xAABBGGRR, so red unpacks first
+fn tile_color(material: u32) -> vec3<f32> {
+    return unpack4x8unorm(palette[material]).rgb;
+}
+
+// Colors are given sRGB-encoded, and shaded in linear light like the CPU does. The output
+// is sampled as linear, unlike the sRGB screen texture the CPU path fills, so it's stored
+// without re-encoding
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn tile_at(cell: vec2<i32>) -> u32 {
+    return tiles[u32(cell.y) * u.map_size.x + u32(cell.x)];
+}
+
+fn in_map(cell: vec2<i32>) -> bool {
+    return all(cell >= vec2<i32>(0)) && all(vec2<u32>(cell) < u.map_size);
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let x = id.x;
+    if x >= u.screen_size.x {
+        return;
+    }
+    let height = u.screen_size.y;
+    // Must match Renderer::xcam with one ray per column: through the column's middle, so
+    // the view is symmetric
+    let xcam = f32(2u * x + 1u) / f32(u.screen_size.x) - 1.0;
+    let ray = u.facing_dir + u.view_plane * xcam;
+    let pos = u.player_pos;
+
+    var cell = vec2<i32>(floor(pos));
+    let delta_dist = vec2<f32>(
+        select(abs(1.0 / ray.x), 1e30, ray.x == 0.0),
+        select(abs(1.0 / ray.y), 1e30, ray.y == 0.0),
+    );
+    let step = vec2<i32>(select(vec2<f32>(1.0), vec2<f32>(-1.0), ray < vec2<f32>(0.0)));
+    var side_dist = select(
+        (vec2<f32>(cell) + 1.0 - pos) * delta_dist,
+        (pos - vec2<f32>(cell)) * delta_dist,
+        ray < vec2<f32>(0.0),
+    );
+
+    var material = 0u;
+    var side = 0u;
+    var max_steps = u.map_size.x + u.map_size.y;
+    if u.wrap != 0u {
+        max_steps = WRAP_MAX_STEPS;
+    }
+    for (var i = 0u; i < max_steps; i++) {
+        if side_dist.x < side_dist.y {
+            side_dist.x += delta_dist.x;
+            cell.x += step.x;
+            side = 0u;
+        } else {
+            side_dist.y += delta_dist.y;
+            cell.y += step.y;
+            side = 1u;
+        }
+        if u.wrap != 0u {
+            let size = vec2<i32>(u.map_size);
+            cell = (cell % size + size) % size;
+        }
+        if !in_map(cell) {
+            break;
+        }
+        if select(side_dist.y - delta_dist.y, side_dist.x - delta_dist.x, side == 0u) > u.max_dist {
+            break;
+        }
+        material = tile_at(cell);
+        if material != 0u {
+            break;
+        }
+    }
+
+    var top = u.horizon;
+    var bottom = top;
+    var wall = vec3<f32>(0.0);
+    var fade = 0.0;
+    if material != 0u {
+        let dist = max(select(side_dist.y - delta_dist.y, side_dist.x - delta_dist.x, side == 0u), 1e-4);
+        let h = u.projection / dist;
+        bottom = u.horizon + h * u.eye_height;
+        top = bottom - h;
+
+        var brightness = FOG_MIN_BRIGHTNESS + (1.0 - FOG_MIN_BRIGHTNESS) * exp(-dist * FOG_FALLOFF);
+        if side == 1u {
+            brightness *= Y_FACE_BRIGHTNESS;
+        }
+        wall = srgb_to_linear(tile_color(material)) * brightness;
+        // Fades into the floor and ceiling toward the view distance, like view_fade
+        fade = smoothstep(0.0, 1.0, (u.max_dist - dist) / (u.max_dist * VIEW_FADE));
+    }
+
+    for (var y = 0u; y < height; y++) {
+        let fy = f32(y);
+        var color = srgb_to_linear(u.floor_color.rgb);
+        if fy < top || (fy < bottom && fy < u.horizon) {
+            color = srgb_to_linear(u.ceiling_color.rgb);
+        }
+        if fy >= top && fy < bottom {
+            color = mix(color, wall, fade);
+        }
+        textureStore(output, vec2<u32>(x, y), vec4<f32>(color, 1.0));
+    }
+}
@@ -1,156 +1,2377 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
-use cgmath::{ElementWise, Vector2, Zero};
+use anyhow::{bail, Context, Result};
+use cgmath::{InnerSpace, Vector2};
+use rayon::prelude::*;
 use winit::dpi::PhysicalSize;
 
+use crate::{
+    asset_pack::AssetPack,
+    camera::Camera,
+    canvas::{self, Canvas},
+    dda::{self, cast_ray, RayHit, WRAP_MAX_STEPS},
+    fixed::Fixed,
+    font,
+    level::LevelSet,
+    lighting::{self, LightMap},
+    map::{Diagonal, Face, Map, Sprite, DOOR_TILE, THIN_WALL_TILE, WINDOW_TILE},
+    palette::Palette,
+    texture::Texture,
+};
+
+/// Where the wall, floor, sky and sprite images are, relative to the assets' root. See
+/// `asset_path`.
+const TEXTURE_DIR: &str = "assets/textures";
+/// A zip of the same files as TEXTURE_DIR, loaded in its place when it exists.
+const TEXTURE_PACK: &str = "assets/textures.zip";
+const FLOOR_TEXTURE: &str = "floor.png";
+const CEILING_TEXTURE: &str = "ceiling.png";
+/// A 360 degree panorama drawn in place of the ceiling, wrapping horizontally.
+const SKY_TEXTURE: &str = "sky.png";
+/// Light level everywhere on a map with lights, before they're added. Maps without lights
+/// are drawn fully lit.
+const AMBIENT_LIGHT: f32 = 0.25;
+const WALL_TEXTURES: [(u8, &str); 6] = [
+    (1, "brick.png"),
+    (2, "stone.png"),
+    (3, "wood.png"),
+    (DOOR_TILE, "door.png"),
+    (THIN_WALL_TILE, "panel.png"),
+    (WINDOW_TILE, "window.png"),
+];
+const SPRITE_TEXTURES: [&str; 2] = ["barrel.png", "lamp.png"];
+/// Held in front of the player, drawn over the bottom center of the screen.
+const WEAPON_TEXTURE: &str = "weapon.png";
+/// How much of the screen height the weapon covers, rounded to a whole number of pixels
+/// per texel.
+const WEAPON_SCREEN_HEIGHT: f32 = 0.4;
+/// How far the weapon sways while walking, as a fraction of the screen height, and how
+/// quickly, in radians of the sway per world unit walked.
+const WEAPON_BOB: f32 = 0.02;
+const WEAPON_BOB_RATE: f32 = 3.;
+/// How many darkened copies of each wall texture are precomputed by default. Walls snap to
+/// the nearest, so more levels band less but cost memory.
+const SHADE_LEVELS: usize = 32;
+/// Pixels per map cell on the minimap, and its offset from the top-left corner.
+const MINIMAP_CELL: i32 = 4;
+const MINIMAP_MARGIN: i32 = 8;
+const MINIMAP_PLAYER_SIZE: i32 = 3;
+const MINIMAP_EMPTY_COLOR: u32 = 0xFF101010;
+const MINIMAP_PLAYER_COLOR: u32 = 0xFFFFFFFF;
+const MINIMAP_FACING_COLOR: u32 = 0xFF00FFFF;
+/// Length of the facing line on the minimap, in map cells.
+const MINIMAP_FACING_LENGTH: f32 = 2.;
+/// Screen columns whose rays are kept for debugging, as fractions of the width: the left
+/// edge, the center and the right edge. They're drawn on the minimap in their own color.
+const DEBUG_RAY_COLUMNS: [f32; 3] = [0., 0.5, 1.];
+const DEBUG_RAY_COLOR: u32 = 0xFF0080FF;
+/// Length of each crosshair arm from the center pixel, and the colors it's drawn in over
+/// dark and light backgrounds respectively.
+const CROSSHAIR_SIZE: i32 = 4;
+const CROSSHAIR_LIGHT_COLOR: u32 = 0xFFFFFFFF;
+const CROSSHAIR_DARK_COLOR: u32 = 0xFF000000;
+/// Sprites closer than this to the camera plane are skipped rather than drawn enormous.
+const SPRITE_NEAR_CLIP: f32 = 0.1;
+/// The stretch before the sprite LOD distance over which sprites' colors fade into their
+/// texture's average, as a fraction of the distance, so they don't pop into a block.
+const SPRITE_LOD_FADE: f32 = 0.2;
+/// Pixels filled per task by `clear`, large enough that spreading them over threads pays.
+const CLEAR_CHUNK: usize = 1 << 16;
+/// How close to a tile edge a wall column has to be, as a fraction of the tile, for
+/// wireframe mode to darken it, and how much it's darkened, out of 256.
+const WIREFRAME_EDGE: f32 = 0.03;
+const WIREFRAME_EDGE_SHADE: u32 = 96;
+/// The distance, in tiles, that depth mode draws black. Anything nearer is lighter.
+const DEPTH_RANGE: f32 = 16.;
+/// The default view distance, in world units. Bounds how far a ray over open ground can go,
+/// and is far enough that walls have long since fogged to their dimmest.
+pub const MAX_VIEW_DISTANCE: f32 = 64.;
+
+/// Colors that don't come from textures, all in the pixel buffer's 0xAABBGGRR format
+/// except the clear color, which is linear like the surface it clears, and how far can
+/// be seen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderConfig {
+    /// Flat floor and ceiling colors, used when there's no texture for them.
+    pub floor_color: u32,
+    pub ceiling_color: u32,
+    /// What the window is cleared to before the frame is drawn over it.
+    pub clear_color: wgpu::Color,
+    /// How far rays go looking for walls, in world units. Walls and sprites fade into the
+    /// floor and ceiling over the last stretch, and past it there's only floor and ceiling.
+    pub max_view_distance: f32,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            floor_color: 0xFF404040,
+            ceiling_color: 0xFF202020,
+            clear_color: wgpu::Color {
+                r: 0.14,
+                g: 0.,
+                b: 0.,
+                a: 1.,
+            },
+            max_view_distance: MAX_VIEW_DISTANCE,
+        }
+    }
+}
+
+/// How the world is drawn. Everything but Textured is for debugging map geometry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Walls, floor, ceiling and sky with their textures where they have them.
+    #[default]
+    Textured,
+    /// Every surface in its flat palette color, walls by their own tile id even when
+    /// animated, so each tile id reads as one solid color.
+    FlatColor,
+    /// FlatColor with the edges between wall tiles darkened, so each cell stands out.
+    Wireframe,
+    /// Distance from the camera plane in grayscale, white up close and black from
+    /// DEPTH_RANGE on, so walls show the depth they leave in the depth buffer. See-through
+    /// tiles and the sky aren't drawn.
+    Depth,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 4] = [
+        RenderMode::Textured,
+        RenderMode::FlatColor,
+        RenderMode::Wireframe,
+        RenderMode::Depth,
+    ];
+
+    /// The mode after this one in ALL, wrapping back to the first.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
 pub struct Renderer {
     camera: Rc<RefCell<Camera>>,
+    /// The camera the frame being drawn is seen from: the shared one, or whatever
+    /// `render_from` was given.
+    view: Camera,
+    map: Rc<RefCell<Map>>,
     size: PhysicalSize<u32>,
     pixels: Vec<u32>,
+    textures: HashMap<u8, Texture>,
+    floor_texture: Option<Texture>,
+    ceiling_texture: Option<Texture>,
+    sky_texture: Option<Texture>,
+    sprite_textures: Vec<Texture>,
+    sprites: Vec<Sprite>,
+    /// How far away, in world units, sprites are drawn as a block of their texture's
+    /// average color rather than textured.
+    sprite_lod_distance: f32,
+    weapon_texture: Option<Texture>,
+    /// How far the player has walked, in world units, wrapped to one sway of the weapon.
+    walked: f32,
+    /// Perpendicular wall distance per screen column, filled by the wall pass.
+    depth: Vec<f32>,
+    show_minimap: bool,
+    show_crosshair: bool,
+    show_sky: bool,
+    /// Draw the debug columns' rays on the minimap.
+    show_rays: bool,
+    /// Where each of the debug columns' rays hit a wall in the last render, or None if it
+    /// left the map.
+    ray_hits: Vec<Option<Vector2<f32>>>,
+    levels: LevelSet,
+    /// See-through wall slices in front of each column's opaque wall, nearest first,
+    /// kept from the wall pass so sprites can be drawn behind them.
+    glass: Vec<Vec<WallSlice>>,
+    /// The rows changed since the last `take_dirty_rows`, empty if none have been.
+    dirty_rows: Range<usize>,
+    /// The last frame as `render` finished it, before anything was drawn over it, so the
+    /// next can be compared against it row by row. Empty until the first render.
+    frame: Vec<u32>,
+    /// The rows `draw_text`, `fill_rect` and `clear` have drawn over `frame` since.
+    overdrawn: Range<usize>,
+    /// Rebuilt from the map's lights whenever they change; None when it has no lights,
+    /// leaving it fully lit.
+    light_map: Option<LightMap>,
+    /// The `Map::lights_revision` the light map was built from, or None before the first.
+    lights_revision: Option<u64>,
+    config: RenderConfig,
+    /// Flat tile colors, reset to the defaults plus the map's own whenever a level loads.
+    palette: Palette,
+    recording: Option<Recording>,
+    /// Cast rays in fixed point rather than floats, so frames are identical on every machine.
+    fixed_point: bool,
+    /// How many darkened copies of each wall texture there are. Below 2 there are none.
+    shade_levels: usize,
+    /// How many adjacent screen columns share each ray, 1 casting one per column.
+    ray_density: usize,
+    /// How many buffer pixels wide and tall each screen pixel is. Above 1 the frame is drawn
+    /// that much larger and averaged back down before upload, smoothing wall edges.
+    supersample: u32,
+    stats: RenderStats,
+    render_mode: RenderMode,
+    /// Draws over each frame once the renderer is done with it. See `set_overlay`.
+    overlay: Option<Overlay>,
+    ray_cache: Option<RayCache>,
+    /// What the finished frame was drawn from, or None if it can't be reused: it was cleared
+    /// since, there's an overlay that might draw something new, or caching is off.
+    frame_key: Option<FrameKey>,
+    /// Reuse the last frame's rays, or the whole frame, when nothing they depend on has
+    /// changed.
+    ray_caching: bool,
 }
 
-#[rustfmt::skip]
-const MAP_DATA: [u8; 15*15] = [
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 2, 0, 0, 3, 3, 3, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-];
+/// A callback given each finished frame's RGBA bytes, and its width and height in pixels.
+pub type Overlay = Box<dyn FnMut(&mut [u8], u32, u32)>;
 
-pub struct Camera {
-    pub player_pos: Vector2<f32>,
-    pub facing_dir: Vector2<f32>,
-    pub view_plane: Vector2<f32>,
+/// Counts from the last frame's wall pass, for finding maps where rays travel a long way.
+/// Each ray is counted once however many columns it covers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub rays: usize,
+    /// Cells entered by all rays together.
+    pub dda_steps: usize,
+    /// The most cells any one ray entered.
+    pub max_steps: usize,
+    /// Rays that left the map, or gave up on a wrapping one, without hitting a wall.
+    pub rays_exited: usize,
+    /// Whether the rays were reused from the frame before rather than cast again, the
+    /// camera and map not having moved since.
+    pub cached: bool,
+    /// Whether the whole frame before was reused, nothing in it having changed, so nothing
+    /// was drawn at all. The other counts are that frame's.
+    pub frame_cached: bool,
 }
 
-pub struct Hit {
-    material: u8,
-    side: u8,
-    point: Vector2<f32>,
-    dist: f32,
+/// Everything the wall pass's rays depend on, so they're only cast again when it changes.
+#[derive(Clone, Copy, PartialEq)]
+struct RayKey {
+    position: Vector2<f32>,
+    direction: Vector2<f32>,
+    plane: Vector2<f32>,
+    map_revision: u64,
+    width: usize,
+    density: usize,
+    fixed_point: bool,
+    max_dist: f32,
 }
-impl Default for Hit {
-    fn default() -> Self {
-        Hit {
-            material: 0,
-            side: 0,
-            point: Vector2::zero(),
-            dist: 0.,
+
+/// Everything else a finished frame depends on, so a frame that would come out the same as
+/// the last isn't drawn at all.
+#[derive(Clone, PartialEq)]
+struct FrameKey {
+    rays: RayKey,
+    camera: Camera,
+    height: u32,
+    lights_revision: u64,
+    /// The map's time, if anything on it moves with it.
+    time: Option<f32>,
+    sprites: Vec<Sprite>,
+    sprite_lod_distance: f32,
+    walked: f32,
+    render_mode: RenderMode,
+    show_minimap: bool,
+    show_crosshair: bool,
+    show_sky: bool,
+    show_rays: bool,
+    shade_levels: usize,
+    config: RenderConfig,
+    palette: Palette,
+}
+
+/// The last wall pass's rays: each one's hit, and the see-through faces in front of it.
+struct RayCache {
+    key: RayKey,
+    hits: Vec<(RayHit, Vec<RayHit>)>,
+}
+
+/// Where rendered frames are being saved, and how many have been so far.
+struct Recording {
+    dir: PathBuf,
+    frames: u32,
+}
+
+/// The part of a screen column covered by the wall its ray hit.
+#[derive(Clone)]
+struct WallSlice {
+    hit: RayHit,
+    /// Unclipped top edge, kept so texture rows stay aligned when the wall overflows the screen.
+    top: f32,
+    /// Screen pixels per world unit of wall height at this distance.
+    unit: f32,
+    y0: usize,
+    y1: usize,
+    /// Per-channel brightness out of 256, combining fog, face shading and light.
+    shade: [u32; 3],
+    /// The point lights' level where the ray hit, white on a map without lights.
+    light: [f32; 3],
+    /// The shaded color of an untextured slice, which is the same all the way down.
+    flat: Option<u32>,
+    /// The tile id whose texture or color is drawn, which differs from the material's while
+    /// it's animated.
+    texture: u8,
+    /// Which of the texture's precomputed darkened copies to draw from, when it has them and
+    /// the slice's light is white. Otherwise each texel is tinted by `shade` as it's drawn.
+    shade_level: Option<usize>,
+    /// How much of the slice shows, out of 255, as it fades out toward the view distance.
+    fade: u8,
+}
+
+impl WallSlice {
+    fn new(
+        hit: RayHit,
+        projection: f32,
+        horizon: f32,
+        eye_height: f32,
+        height: usize,
+        tile_height: f32,
+        light: [f32; 3],
+    ) -> Self {
+        if hit.material == 0 {
+            return Self {
+                hit,
+                top: 0.,
+                unit: 0.,
+                y0: 0,
+                y1: 0,
+                shade: [0; 3],
+                light,
+                flat: None,
+                texture: 0,
+                shade_level: None,
+                fade: 255,
+            };
+        }
+        // Walls stand on the floor, which is eye_height below eye level, so taller or
+        // shorter tiles only move the top edge
+        let unit = projection / hit.dist.max(1e-4);
+        let bottom = horizon + unit * eye_height;
+        let top = bottom - unit * tile_height;
+        Self {
+            top,
+            unit,
+            y0: top.max(0.) as usize,
+            y1: (bottom.max(0.) as usize).min(height),
+            shade: Renderer::shade(lighting::wall_brightness(hit.side, hit.dist), light),
+            light,
+            flat: None,
+            texture: hit.material,
+            shade_level: None,
+            fade: 255,
+            hit,
+        }
+    }
+
+    /// Draws the slice with `texture`'s texture, precomputing the color from the palette if it
+    /// has none, so drawing it is a plain store rather than a lookup and tint per pixel.
+    fn with_textures(
+        mut self,
+        texture: u8,
+        textures: &HashMap<u8, Texture>,
+        palette: &Palette,
+    ) -> Self {
+        self.texture = texture;
+        match textures.get(&texture) {
+            None => {
+                // Shaded for its face and distance, then lit, which white light leaves alone
+                let color = lighting::shade(palette.get(texture), self.hit.side, self.hit.dist);
+                self.flat = Some(lighting::tint(color, Renderer::shade(1., self.light)));
+            }
+            Some(texture) if texture.shade_levels() > 0 => {
+                // Colored light can't be looked up, since the copies are only darkened evenly
+                let [r, g, b] = self.shade;
+                if r == g && g == b {
+                    let top = texture.shade_levels() - 1;
+                    let level = (r.min(256) as f32 / 256. * top as f32).round() as usize;
+                    self.shade_level = Some(level.min(top));
+                }
+            }
+            Some(_) => {}
+        }
+        self
+    }
+
+    fn color(&self, textures: &HashMap<u8, Texture>, y: usize) -> u32 {
+        let color = self.unfaded_color(textures, y);
+        if self.fade == 255 {
+            return color;
+        }
+        let alpha = (color >> 24) * self.fade as u32 / 255;
+        color & 0x00FFFFFF | alpha << 24
+    }
+
+    /// The slice's color at row `y` drawn over `background`, which is only worked out if the
+    /// slice is fading and lets some of it through.
+    fn color_over(
+        &self,
+        textures: &HashMap<u8, Texture>,
+        y: usize,
+        background: impl FnOnce() -> u32,
+    ) -> u32 {
+        let color = self.color(textures, y);
+        if self.fade == 255 {
+            color
+        } else {
+            canvas::blend(background(), color)
+        }
+    }
+
+    fn unfaded_color(&self, textures: &HashMap<u8, Texture>, y: usize) -> u32 {
+        match (self.flat, textures.get(&self.texture)) {
+            (None, Some(texture)) => {
+                // The texture repeats once per world unit, so tall walls tile it vertically
+                let v = ((y as f32 + 0.5 - self.top) / self.unit).rem_euclid(1.);
+                let tex_y = ((v * texture.height() as f32) as usize).min(texture.height() - 1);
+                let tex_x = texture.column(self.hit.wall_x);
+                match self.shade_level {
+                    Some(level) => texture.shaded_texel(level, tex_x, tex_y),
+                    None => lighting::tint(texture.texel(tex_x, tex_y), self.shade),
+                }
+            }
+            // Slices without a texture get their flat color in `with_textures`
+            (flat, _) => flat.unwrap_or_default(),
         }
     }
 }
 
 impl Renderer {
-    pub fn new(camera: Rc<RefCell<Camera>>, size: PhysicalSize<u32>) -> Self {
+    /// A renderer with its textures from TEXTURE_PACK if there is one, or else from the
+    /// files in TEXTURE_DIR.
+    pub fn new(
+        camera: Rc<RefCell<Camera>>,
+        map: Rc<RefCell<Map>>,
+        size: PhysicalSize<u32>,
+    ) -> Result<Self> {
+        let pack = asset_path(TEXTURE_PACK);
+        if pack.exists() {
+            let pack = AssetPack::open(pack)?;
+            return Self::from_pack(camera, map, size, &pack);
+        }
+        let texture_dir = asset_path(TEXTURE_DIR);
+        Self::with_textures(camera, map, size, |file| {
+            Texture::load(texture_dir.join(file))
+        })
+    }
+
+    /// A renderer with its textures from `pack`, under the same names as the files in
+    /// TEXTURE_DIR.
+    pub fn from_pack(
+        camera: Rc<RefCell<Camera>>,
+        map: Rc<RefCell<Map>>,
+        size: PhysicalSize<u32>,
+        pack: &AssetPack,
+    ) -> Result<Self> {
+        Self::with_textures(camera, map, size, |name| {
+            Ok(Texture::from_image(&pack.image(name)?))
+        })
+    }
+
+    /// A renderer with every texture it draws loaded by `load`, given each one's file name.
+    /// Decoding is most of the startup time and the files are independent, so they're all
+    /// loaded in parallel. If any fail, one of their errors is returned.
+    fn with_textures(
+        camera: Rc<RefCell<Camera>>,
+        map: Rc<RefCell<Map>>,
+        size: PhysicalSize<u32>,
+        load: impl Fn(&str) -> Result<Texture> + Sync,
+    ) -> Result<Self> {
+        let files: Vec<&str> = WALL_TEXTURES
+            .iter()
+            .map(|&(_, file)| file)
+            .chain([FLOOR_TEXTURE, CEILING_TEXTURE, SKY_TEXTURE, WEAPON_TEXTURE])
+            .chain(SPRITE_TEXTURES)
+            .collect();
+        let mut loaded: HashMap<&str, Texture> = files
+            .into_par_iter()
+            .map(|file| Ok((file, load(file)?)))
+            .collect::<Result<_>>()?;
+        let mut take = |file: &str| {
+            loaded
+                .remove(file)
+                .with_context(|| format!("texture {file} is used more than once"))
+        };
+
+        let mut renderer = Self::untextured(camera, map, size);
+        renderer.textures = WALL_TEXTURES
+            .iter()
+            .map(|&(id, file)| Ok((id, take(file)?)))
+            .collect::<Result<_>>()?;
+        renderer.floor_texture = Some(take(FLOOR_TEXTURE)?);
+        renderer.ceiling_texture = Some(take(CEILING_TEXTURE)?);
+        renderer.sky_texture = Some(take(SKY_TEXTURE)?);
+        renderer.sprite_textures = SPRITE_TEXTURES
+            .iter()
+            .map(|file| take(file))
+            .collect::<Result<_>>()?;
+        renderer.weapon_texture = Some(take(WEAPON_TEXTURE)?);
+        renderer.set_shade_levels(SHADE_LEVELS);
+        Ok(renderer)
+    }
+
+    /// A renderer that loads no assets and draws every surface as a flat color, so frames
+    /// are deterministic and need nothing from disk. Sprites are skipped without textures.
+    pub fn untextured(
+        camera: Rc<RefCell<Camera>>,
+        map: Rc<RefCell<Map>>,
+        size: PhysicalSize<u32>,
+    ) -> Self {
         let buffer_size = size.width * size.height;
+        let (palette, sprites) = {
+            let map = map.borrow();
+            (Palette::for_map(&map), map.sprites().to_vec())
+        };
+        let view = *camera.borrow();
         Self {
             camera,
+            view,
+            map,
             size,
             pixels: vec![0; buffer_size as usize],
+            textures: HashMap::new(),
+            floor_texture: None,
+            ceiling_texture: None,
+            sky_texture: None,
+            sprite_textures: Vec::new(),
+            sprites,
+            sprite_lod_distance: f32::INFINITY,
+            weapon_texture: None,
+            walked: 0.,
+            depth: vec![f32::INFINITY; size.width as usize],
+            show_minimap: true,
+            show_crosshair: true,
+            show_sky: true,
+            show_rays: false,
+            ray_hits: Vec::new(),
+            levels: LevelSet::default(),
+            glass: Vec::new(),
+            dirty_rows: 0..size.height as usize,
+            frame: Vec::new(),
+            overdrawn: 0..0,
+            light_map: None,
+            lights_revision: None,
+            config: RenderConfig::default(),
+            palette,
+            recording: None,
+            fixed_point: false,
+            shade_levels: SHADE_LEVELS,
+            ray_density: 1,
+            supersample: 1,
+            stats: RenderStats::default(),
+            render_mode: RenderMode::default(),
+            overlay: None,
+            ray_cache: None,
+            frame_key: None,
+            ray_caching: true,
         }
     }
 
-    fn write_column(&mut self, x: usize, y0: usize, y1: usize, color: u32) {
-        for y in y0..y1 {
-            self.pixels[y * self.size.width as usize + x] = color;
+    /// A copy of the camera as it is now, for HUDs or sending elsewhere.
+    pub fn camera(&self) -> Camera {
+        *self.camera.borrow()
+    }
+
+    /// The size of the pixel buffer, which is the output size times the supersampling factor.
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// The size of the frame once downsampled, which is what the screen texture should be.
+    pub fn output_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(
+            self.size.width / self.supersample,
+            self.size.height / self.supersample,
+        )
+    }
+
+    /// Reallocates the buffers for a new output size, widening or narrowing the view plane
+    /// with the aspect ratio so the vertical field of view is kept and nothing is stretched.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        let size = PhysicalSize::new(
+            size.width * self.supersample,
+            size.height * self.supersample,
+        );
+        let aspect = |size: PhysicalSize<u32>| size.width as f32 / size.height.max(1) as f32;
+        let mut camera = self.camera.borrow_mut();
+        camera.view_plane *= aspect(size) / aspect(self.size);
+        drop(camera);
+
+        self.size = size;
+        self.pixels = vec![0; (size.width * size.height) as usize];
+        self.dirty_rows = 0..size.height as usize;
+        self.frame = Vec::new();
+        self.overdrawn = 0..0;
+        self.frame_key = None;
+        self.depth = vec![f32::INFINITY; size.width as usize];
+    }
+
+    /// Fills the whole frame with `color` and empties the depth buffer, so a pass drawing
+    /// only part of the frame, or a test, starts from a known background.
+    pub fn clear(&mut self, color: u32) {
+        self.pixels
+            .par_chunks_mut(CLEAR_CHUNK)
+            .for_each(|chunk| chunk.fill(color));
+        self.depth.fill(f32::INFINITY);
+        self.frame_key = None;
+        self.mark_drawn_over(0, self.size.height as i32);
+    }
+
+    /// The perpendicular wall distance for each screen column from the last render.
+    /// Columns whose ray left the map hold infinity.
+    pub fn depth(&self) -> &[f32] {
+        &self.depth
+    }
+
+    pub fn show_minimap(&self) -> bool {
+        self.show_minimap
+    }
+
+    pub fn set_show_minimap(&mut self, show: bool) {
+        self.show_minimap = show;
+    }
+
+    pub fn show_crosshair(&self) -> bool {
+        self.show_crosshair
+    }
+
+    pub fn set_show_crosshair(&mut self, show: bool) {
+        self.show_crosshair = show;
+    }
+
+    /// Whether the sky is drawn instead of the ceiling. Has no effect without a sky texture.
+    pub fn show_sky(&self) -> bool {
+        self.show_sky
+    }
+
+    pub fn set_show_sky(&mut self, show: bool) {
+        self.show_sky = show;
+    }
+
+    /// Whether the left, center and right columns' rays are drawn on the minimap, to debug
+    /// the DDA.
+    pub fn show_rays(&self) -> bool {
+        self.show_rays
+    }
+
+    pub fn set_show_rays(&mut self, show: bool) {
+        self.show_rays = show;
+    }
+
+    /// The world points where the left, center and right columns' rays hit a wall in the last
+    /// render, or None for a ray that left the map.
+    pub fn ray_hits(&self) -> &[Option<Vector2<f32>>] {
+        &self.ray_hits
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    /// Runs `overlay` at the end of every render, after the HUD and before the frame is
+    /// recorded or uploaded, so other code can draw over the top. The frame is the render
+    /// size, so larger than the screen while supersampling. Replaces any overlay already set.
+    pub fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = Some(overlay);
+    }
+
+    /// Removes the overlay, returning it.
+    pub fn take_overlay(&mut self) -> Option<Overlay> {
+        self.overlay.take()
+    }
+
+    /// Counts from the wall pass of the last render.
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    pub fn config(&self) -> &RenderConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: RenderConfig) {
+        self.config = config;
+    }
+
+    /// The flat colors of the current level's tiles.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// The flat color walls of tile id `tile` are drawn in when untextured, in 0xAABBGGRR.
+    /// Lasts until the next level loads.
+    pub fn set_tile_color(&mut self, tile: u8, rgba: u32) {
+        self.palette.set(tile, rgba);
+    }
+
+    pub fn sprites(&self) -> &[Sprite] {
+        &self.sprites
+    }
+
+    pub fn add_sprite(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn clear_sprites(&mut self) {
+        self.sprites.clear();
+    }
+
+    pub fn sprite_lod_distance(&self) -> f32 {
+        self.sprite_lod_distance
+    }
+
+    /// Draws sprites at least `distance` world units away as a flat block of their average
+    /// color over the part of them that isn't transparent, which needs no texture lookups or
+    /// blending, fading into that color over the stretch before. With many distant sprites
+    /// that's cheaper and they're too small to tell. Infinity, the default, always textures
+    /// them.
+    pub fn set_sprite_lod_distance(&mut self, distance: f32) {
+        self.sprite_lod_distance = distance;
+    }
+
+    pub fn levels(&self) -> &LevelSet {
+        &self.levels
+    }
+
+    pub fn levels_mut(&mut self) -> &mut LevelSet {
+        &mut self.levels
+    }
+
+    /// Replaces the level set. The current map isn't touched until the next `load_level`.
+    pub fn set_levels(&mut self, levels: LevelSet) {
+        self.levels = levels;
+    }
+
+    /// Swaps a fresh copy of level `index` into the shared map and moves the camera to its
+    /// spawn point, keeping the field of view.
+    pub fn load_level(&mut self, index: usize) -> Result<()> {
+        if !self.levels.set_current(index) {
+            bail!("no level {index}, there are {}", self.levels.len());
         }
+        let map = self.levels.current_map().clone();
+        let mut camera = self.camera.borrow_mut();
+        let fov = camera.fov();
+        camera.player_pos = map.spawn_pos();
+        camera.facing_dir = map.spawn_dir();
+        self.palette = Palette::for_map(&map);
+        self.sprites = map.sprites().to_vec();
+        *self.map.borrow_mut() = map;
+        camera.set_fov(fov);
+        Ok(())
     }
 
-    fn raycast(&self, x: usize) -> Hit {
-        let camera = self.camera.borrow();
-        let xcam = (2. * (x as f32 / self.size.width as f32)) - 1.;
-        let ray = Vector2::new(
-            camera.facing_dir.x + camera.view_plane.x * xcam,
-            camera.facing_dir.y + camera.view_plane.y * xcam,
+    /// The same DDA as `cast_ray`, stepped in fixed point so a camera and map cast the same
+    /// rays on every machine. The column is given as `x` of `width` rather than as a float
+    /// `xcam` so the ray direction is exact too. Only the results are converted to floats.
+    fn raycast_fixed(
+        camera: &Camera,
+        map: &Map,
+        x: usize,
+        width: usize,
+        max_dist: f32,
+        see_through: &mut Vec<RayHit>,
+    ) -> RayHit {
+        let fixed = |v: Vector2<f32>| (Fixed::from_f32(v.x), Fixed::from_f32(v.y));
+        let to_f32 = |(x, y): (Fixed, Fixed)| Vector2::new(x.to_f32(), y.to_f32());
+        let xcam = Fixed::ratio(2 * x as i64 - width as i64, width as i64);
+        let (dir, plane, pos) = (
+            fixed(camera.facing_dir),
+            fixed(camera.view_plane),
+            fixed(camera.player_pos),
         );
+        let ray = (dir.0 + plane.0 * xcam, dir.1 + plane.1 * xcam);
+        let along_ray = |dist: Fixed| (pos.0 + ray.0 * dist, pos.1 + ray.1 * dist);
 
-        let pos = camera.player_pos;
-        let mut ipos = Vector2::new(pos.x as usize, pos.y as usize);
-        let delta_dist = Vector2::new(ray.x.recip().abs(), ray.y.recip().abs());
-        let mut side_dist = Vector2::new(
-            if ray.x.abs() < 1e-20 {
-                pos.x - ipos.x as f32
-            } else {
-                ipos.x as f32 + 1. - pos.x
-            },
-            if ray.y.abs() < 1e-20 {
-                pos.y - ipos.y as f32
+        let mut ipos = (pos.0.floor().max(0) as usize, pos.1.floor().max(0) as usize);
+        let delta_dist = (ray.0.recip().abs(), ray.1.recip().abs());
+        let first_side = |pos: Fixed, cell: usize, ray: Fixed, delta_dist: Fixed| {
+            let cell = Fixed::from_int(cell as i64);
+            if ray.is_negative() {
+                (pos - cell) * delta_dist
             } else {
-                ipos.y as f32 + 1. - pos.y
-            },
+                (cell + Fixed::ONE - pos) * delta_dist
+            }
+        };
+        let mut side_dist = (
+            first_side(pos.0, ipos.0, ray.0, delta_dist.0),
+            first_side(pos.1, ipos.1, ray.1, delta_dist.1),
         );
 
-        let step = Vector2::new(ray.x.signum() as i32, ray.y.signum() as i32);
+        let step = |ray: Fixed| if ray.is_negative() { -1 } else { 1 };
+        let step = (step(ray.0), step(ray.1));
+        // Cells wrap the same way as in Dda::wrapping
+        let advance = |cell: usize, step: isize, size: usize| {
+            if map.wraps() {
+                Some((cell as isize + step).rem_euclid(size as isize) as usize)
+            } else {
+                cell.checked_add_signed(step)
+            }
+        };
+        let mut steps = 0;
+
+        // How far from `local` in a diagonal wall's cell the ray meets its line, as in
+        // Diagonal::intersect. A parallel ray meets it at HUGE
+        let diagonal_dist = |diagonal: Diagonal, local: (Fixed, Fixed)| match diagonal {
+            Diagonal::TopLeft | Diagonal::BottomRight => {
+                (Fixed::ONE - local.0 - local.1) * (ray.0 + ray.1).recip()
+            }
+            Diagonal::TopRight | Diagonal::BottomLeft => {
+                (local.1 - local.0) * (ray.0 - ray.1).recip()
+            }
+        };
+        let diagonal_hit = |material, diagonal: Diagonal, dist: Fixed, local, steps| RayHit {
+            material,
+            side: dda::DIAGONAL_SIDE,
+            point: map.wrap_position(to_f32(along_ray(dist))),
+            dist: dist.to_f32(),
+            wall_x: diagonal.wall_x(to_f32(local)),
+            face: None,
+            steps,
+        };
+        if let Some(diagonal) = map.diagonal(ipos.0, ipos.1) {
+            let local = (pos.0.fract(), pos.1.fract());
+            let dist = diagonal_dist(diagonal, local);
+            if !dist.is_negative() && dist <= side_dist.0.min(side_dist.1) {
+                let material = map.get(ipos.0, ipos.1).unwrap_or(0);
+                let local = (local.0 + ray.0 * dist, local.1 + ray.1 * dist);
+                return diagonal_hit(material, diagonal, dist, local, 0);
+            }
+        }
 
-        let mut hit = Hit::default();
+        let mut hit = RayHit::default();
+        let mut previous = 0;
 
         while hit.material == 0 {
-            if side_dist.x < side_dist.y {
-                side_dist.x += delta_dist.x;
-                ipos.x = (ipos.x as i32 + step.x) as usize;
+            if map.wraps() && steps == WRAP_MAX_STEPS {
+                return hit;
+            }
+            steps += 1;
+            hit.steps = steps;
+            let next = if side_dist.0 < side_dist.1 {
+                side_dist.0 += delta_dist.0;
                 hit.side = 0;
+                advance(ipos.0, step.0, map.width()).map(|x| (x, ipos.1))
             } else {
-                side_dist.y += delta_dist.y;
-                ipos.y = (ipos.y as i32 + step.y) as usize;
+                side_dist.1 += delta_dist.1;
                 hit.side = 1;
+                advance(ipos.1, step.1, map.height()).map(|y| (ipos.0, y))
+            };
+            hit.face = Face::hit(hit.side, to_f32(ray));
+
+            // Fixed-point values convert to floats exactly, so the cutoff is exact too
+            let entry = match hit.side {
+                0 => side_dist.0 - delta_dist.0,
+                _ => side_dist.1 - delta_dist.1,
+            };
+            if entry.to_f32() > max_dist {
+                return RayHit {
+                    steps,
+                    ..RayHit::default()
+                };
+            }
+
+            match next.and_then(|(x, y)| Some((x, y, map.get(x, y)?))) {
+                Some((x, y, material)) => {
+                    ipos = (x, y);
+                    hit.material = material;
+                }
+                None => return hit,
             }
 
-            hit.material = MAP_DATA[ipos.y * 15 + ipos.x];
+            if let Some(open) = map.thin_wall(ipos.0, ipos.1) {
+                // Along the plane the map gives it, worked from the middle of the ray's
+                // path through the cell, like cast_ray
+                let side = map.thin_wall_side(ipos.0, ipos.1);
+                let exit = side_dist.0.min(side_dist.1);
+                let mid = (entry + exit).half();
+                let mid_point = along_ray(mid);
+                let (across, speed) = match side {
+                    0 => (mid_point.0.fract(), ray.0),
+                    _ => (mid_point.1.fract(), ray.1),
+                };
+                let dist = mid + (Fixed::ONE.half() - across) * speed.recip();
+                if entry <= dist && dist <= exit {
+                    let point = along_ray(dist);
+                    let wall_x = match side {
+                        0 => point.1.fract(),
+                        _ => point.0.fract(),
+                    };
+                    let open = Fixed::from_f32(open);
+                    if wall_x >= open {
+                        hit.side = side;
+                        hit.face = Face::hit(side, to_f32(ray));
+                        hit.dist = dist.to_f32();
+                        hit.point = map.wrap_position(to_f32(point));
+                        hit.wall_x = (wall_x - open).to_f32();
+                        return hit;
+                    }
+                }
+                hit.material = 0;
+                continue;
+            }
+
+            if let Some(diagonal) = map.diagonal(ipos.0, ipos.1) {
+                // Worked from the middle of the ray's path through the cell, like cast_ray
+                let exit = side_dist.0.min(side_dist.1);
+                let mid = (entry + exit).half();
+                let mid_point = along_ray(mid);
+                let local = (mid_point.0.fract(), mid_point.1.fract());
+                let at = |dist: Fixed| {
+                    (
+                        local.0 + ray.0 * (dist - mid),
+                        local.1 + ray.1 * (dist - mid),
+                    )
+                };
+                // Fixed-point values convert to floats exactly, so this test is exact too
+                if !diagonal.is_solid(to_f32(at(entry))) {
+                    let dist = mid + diagonal_dist(diagonal, local);
+                    if entry <= dist && dist <= exit {
+                        return diagonal_hit(hit.material, diagonal, dist, at(dist), steps);
+                    }
+                    hit.material = 0;
+                    continue;
+                }
+            }
+
+            if hit.material != 0 {
+                hit.dist = entry.to_f32();
+                hit.point = map.wrap_position(to_f32(along_ray(entry)));
+                hit.wall_x = dda::wall_x(hit.point, to_f32(ray), hit.side);
+            }
+
+            let material = hit.material;
+            if material != 0 && !map.is_opaque(material) {
+                if material != previous {
+                    see_through.push(hit);
+                }
+                hit.material = 0;
+            }
+            previous = material;
         }
 
-        hit.point = pos.add_element_wise(side_dist);
-        hit.dist = match hit.side {
-            0 => side_dist.x - delta_dist.x,
-            _ => side_dist.y - delta_dist.y,
+        hit
+    }
+
+    /// Where across the view plane the `ray`th ray of a `width` column frame is cast, from -1
+    /// at the left edge to 1 at the right. Each ray covers `density` columns and is cast
+    /// through the middle of them, so the view is symmetric.
+    fn xcam(ray: usize, density: usize, width: usize) -> f32 {
+        (2 * ray * density + density) as f32 / width as f32 - 1.
+    }
+
+    /// The gray depth mode draws something `dist` tiles from the camera plane.
+    fn depth_color(dist: f32) -> u32 {
+        let level = ((1. - dist / DEPTH_RANGE).clamp(0., 1.) * 255.).round() as u32;
+        0xFF000000 | (level * 0x010101)
+    }
+
+    /// Combines a brightness in [0, 1] with a light level into per-channel brightness out of 256.
+    fn shade(brightness: f32, light: [f32; 3]) -> [u32; 3] {
+        light.map(|channel| (256. * brightness * channel) as u32)
+    }
+
+    /// Screen pixels per tile of depth at distance 1. Walls and sprites are a world unit
+    /// tall, so they shrink as tiles stand for more world units.
+    fn projection(&self) -> f32 {
+        self.view.projection(self.size.width) / self.map.borrow().world_scale()
+    }
+
+    pub fn render(&mut self) {
+        self.clamp_camera();
+        self.render_from(self.camera());
+    }
+
+    /// Renders a frame seen from `camera` rather than the shared camera, which is left alone.
+    /// It's moved onto the map if it's off it, like the shared camera is.
+    ///
+    /// A frame that would come out the same as the last isn't drawn again. Only the rows
+    /// drawn over since are put back, so a still scene costs next to nothing.
+    pub fn render_from(&mut self, camera: Camera) {
+        let map = self.map.borrow();
+        self.view = Camera {
+            player_pos: map.clamp_position(camera.player_pos),
+            ..camera
         };
+        if self.lights_revision != Some(map.lights_revision()) {
+            self.light_map = (!map.lights().is_empty()).then(|| LightMap::new(&map, AMBIENT_LIGHT));
+            self.lights_revision = Some(map.lights_revision());
+        }
+        let key = (self.ray_caching && self.overlay.is_none()).then(|| self.frame_key(&map));
+        drop(map);
+        if key.is_some() && key == self.frame_key {
+            self.restore_frame();
+            self.stats.cached = true;
+            self.stats.frame_cached = true;
+            self.record_frame();
+            return;
+        }
+        self.render_world();
+        self.render_sprites();
+        self.render_weapon();
+        if self.show_crosshair {
+            self.render_crosshair();
+        }
+        if self.show_minimap {
+            self.render_minimap();
+        }
+        if let Some(overlay) = &mut self.overlay {
+            let bytes = bytemuck::cast_slice_mut::<u32, u8>(&mut self.pixels);
+            overlay(bytes, self.size.width, self.size.height);
+        }
+        self.mark_changed_rows();
+        self.frame_key = key;
+        self.record_frame();
+    }
 
-        hit
+    /// Everything the wall pass's rays depend on, seen from `view`.
+    fn ray_key(&self, map: &Map) -> RayKey {
+        RayKey {
+            position: self.view.player_pos,
+            direction: self.view.facing_dir,
+            plane: self.view.view_plane,
+            map_revision: map.revision(),
+            width: self.size.width as usize,
+            density: self.ray_density,
+            fixed_point: self.fixed_point,
+            max_dist: self.config.max_view_distance / map.world_scale(),
+        }
+    }
+
+    /// Everything a frame seen from `view` depends on.
+    fn frame_key(&self, map: &Map) -> FrameKey {
+        FrameKey {
+            rays: self.ray_key(map),
+            camera: self.view,
+            height: self.size.height,
+            lights_revision: map.lights_revision(),
+            time: map.is_animated().then(|| map.time()),
+            sprites: self.sprites.clone(),
+            sprite_lod_distance: self.sprite_lod_distance,
+            walked: self.walked,
+            render_mode: self.render_mode,
+            show_minimap: self.show_minimap,
+            show_crosshair: self.show_crosshair,
+            show_sky: self.show_sky,
+            show_rays: self.show_rays,
+            shade_levels: self.shade_levels,
+            config: self.config,
+            palette: self.palette.clone(),
+        }
+    }
+
+    /// Puts the last finished frame back over the rows drawn on since, marking them dirty,
+    /// for a frame that would otherwise come out the same.
+    fn restore_frame(&mut self) {
+        let overdrawn = std::mem::replace(&mut self.overdrawn, 0..0);
+        let width = self.size.width as usize;
+        let rows = overdrawn.start * width..overdrawn.end * width;
+        self.pixels[rows.clone()].copy_from_slice(&self.frame[rows]);
+        self.mark_dirty(overdrawn.start as i32, overdrawn.end as i32);
+    }
+
+    /// Pulls the camera back onto the map if something has pushed it off, so every ray starts
+    /// in a tile that exists. Collision should keep this from ever firing, so it warns when
+    /// it does rather than hiding the bug.
+    pub fn clamp_camera(&self) {
+        let map = self.map.borrow();
+        let mut camera = self.camera.borrow_mut();
+        let clamped = map.clamp_position(camera.player_pos);
+        if clamped != camera.player_pos {
+            log::warn!(
+                "camera at ({}, {}) was off the map, moved to ({}, {})",
+                camera.player_pos.x,
+                camera.player_pos.y,
+                clamped.x,
+                clamped.y
+            );
+            camera.player_pos = clamped;
+        }
+    }
+
+    /// Starts saving every rendered frame into `dir` as numbered PNGs, creating it if needed.
+    pub fn enable_recording(&mut self, dir: impl Into<PathBuf>) -> Result<()> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create recording directory {}", dir.display()))?;
+        self.recording = Some(Recording { dir, frames: 0 });
+        Ok(())
     }
 
-    fn material_to_color(mat: u8, side: u8) -> u32 {
-        let mut color = match mat {
-            1 => 0xFF0000FF,
-            2 => 0xFF00FF00,
-            3 => 0xFFFF0000,
-            _ => 0xFFFF00FF,
+    /// Stops recording, returning how many frames were saved, or None if it wasn't recording.
+    pub fn disable_recording(&mut self) -> Option<u32> {
+        self.recording.take().map(|recording| recording.frames)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Whether rays are cast in fixed point, for replays and networking that need every
+    /// machine to see the same walls. Floats are the default.
+    pub fn fixed_point(&self) -> bool {
+        self.fixed_point
+    }
+
+    pub fn set_fixed_point(&mut self, enabled: bool) {
+        self.fixed_point = enabled;
+    }
+
+    /// Whether a frame from the same place as the last reuses its rays, and one that would
+    /// come out the same reuses the whole frame. On by default; off draws every frame in
+    /// full, for timing it.
+    pub fn ray_caching(&self) -> bool {
+        self.ray_caching
+    }
+
+    pub fn set_ray_caching(&mut self, enabled: bool) {
+        self.ray_caching = enabled;
+        if !enabled {
+            self.ray_cache = None;
+            self.frame_key = None;
+        }
+    }
+
+    pub fn shade_levels(&self) -> usize {
+        self.shade_levels
+    }
+
+    /// Rebuilds every wall texture with `levels` darkened copies, so walls under white light
+    /// are shaded by lookup. Fewer than 2 frees them and shades every texel as it's drawn,
+    /// which is slower but exact.
+    pub fn set_shade_levels(&mut self, levels: usize) {
+        self.shade_levels = levels;
+        for texture in self.textures.values_mut() {
+            texture.build_shades(levels, |texel, brightness| {
+                lighting::tint(texel, Self::shade(brightness, [1.; 3]))
+            });
+        }
+    }
+
+    pub fn ray_density(&self) -> usize {
+        self.ray_density
+    }
+
+    /// Casts one ray per `density` screen columns and stretches each across them, trading
+    /// horizontal detail for fewer rays. 1 casts a ray per column.
+    pub fn set_ray_density(&mut self, density: usize) {
+        self.ray_density = density.max(1);
+    }
+
+    pub fn supersample(&self) -> u32 {
+        self.supersample
+    }
+
+    /// Renders at `factor` times the output size along each axis, reallocating the buffers.
+    /// 1 turns supersampling off.
+    pub fn set_supersample(&mut self, factor: u32) {
+        let output = self.output_size();
+        self.supersample = factor.max(1);
+        self.resize(output);
+    }
+
+    /// Averages each supersampled block of the frame into one pixel of `out`, a tightly
+    /// packed RGBA frame at the output size. Only the given output rows are written.
+    pub fn downsample(&self, rows: Range<usize>, out: &mut [u8]) {
+        let output = self.output_size();
+        debug_assert_eq!(out.len(), (output.width * output.height * 4) as usize);
+        let factor = self.supersample as usize;
+        let width = self.size.width as usize;
+        let out_row_bytes = width / factor * 4;
+        let area = (factor * factor) as u32;
+        out[rows.start * out_row_bytes..rows.end * out_row_bytes]
+            .par_chunks_mut(out_row_bytes)
+            .zip(
+                self.pixels[rows.start * factor * width..rows.end * factor * width]
+                    .par_chunks(width * factor),
+            )
+            .for_each(|(out_row, block)| {
+                for (x, out_pixel) in out_row.chunks_exact_mut(4).enumerate() {
+                    let mut sum = [0u32; 4];
+                    for row in block.chunks(width) {
+                        for pixel in &row[x * factor..(x + 1) * factor] {
+                            for (sum, byte) in sum.iter_mut().zip(pixel.to_le_bytes()) {
+                                *sum += byte as u32;
+                            }
+                        }
+                    }
+                    for (out, sum) in out_pixel.iter_mut().zip(sum) {
+                        *out = ((sum + area / 2) / area) as u8;
+                    }
+                }
+            });
+    }
+
+    /// Saves the frame just rendered if recording. A frame that fails to save stops the
+    /// recording rather than failing every frame after it.
+    fn record_frame(&mut self) {
+        let Some(recording) = &self.recording else {
+            return;
         };
-        if side == 1 {
-            let br = ((color & 0xFF00FF) * 0xC0) >> 8;
-            let g = ((color & 0x00FF00) * 0xC0) >> 8;
-            color = 0xFF000000 | (br & 0xFF00FF) | (g & 0x00FF00);
+        let path = recording
+            .dir
+            .join(format!("frame-{:06}.png", recording.frames));
+        match self.save_screenshot(&path) {
+            Ok(()) => {
+                if let Some(recording) = &mut self.recording {
+                    recording.frames += 1;
+                }
+            }
+            Err(err) => {
+                log::error!("{err:#}, stopping recording");
+                self.recording = None;
+            }
         }
-        color
     }
 
-    pub fn render(&mut self) {
+    /// The rows changed since the last call, or None if none have, so only they need
+    /// uploading. Everything is stale after a resize.
+    pub fn take_dirty_rows(&mut self) -> Option<Range<usize>> {
+        let rows = std::mem::replace(&mut self.dirty_rows, 0..0);
+        (!rows.is_empty()).then_some(rows)
+    }
+
+    /// Marks the rows of the frame just rendered that differ from the last one dirty, along
+    /// with any drawn over since, which the last upload had something else in. A frame that
+    /// hasn't changed marks nothing, so it isn't uploaded again.
+    fn mark_changed_rows(&mut self) {
+        let overdrawn = std::mem::replace(&mut self.overdrawn, 0..0);
+        if self.frame.len() != self.pixels.len() {
+            self.frame.clone_from(&self.pixels);
+            self.mark_dirty(0, self.size.height as i32);
+            return;
+        }
+        let width = self.size.width as usize;
+        let row = |y: usize| y * width..(y + 1) * width;
+        let changed =
+            |y: &usize| overdrawn.contains(y) || self.pixels[row(*y)] != self.frame[row(*y)];
+        let height = self.size.height as usize;
+        let Some(top) = (0..height).find(changed) else {
+            return;
+        };
+        let bottom = (0..height).rfind(changed).unwrap_or(top) + 1;
+        let rows = top * width..bottom * width;
+        self.frame[rows.clone()].copy_from_slice(&self.pixels[rows]);
+        self.mark_dirty(top as i32, bottom as i32);
+    }
+
+    /// Adds the rows from `top` up to `bottom` to the dirty ones, clipped to the screen.
+    fn mark_dirty(&mut self, top: i32, bottom: i32) {
+        let rows = self.clip_rows(top, bottom);
+        self.dirty_rows = union(&self.dirty_rows, rows);
+    }
+
+    /// Marks rows drawn over the finished frame dirty, and remembers that they no longer
+    /// match it.
+    fn mark_drawn_over(&mut self, top: i32, bottom: i32) {
+        let rows = self.clip_rows(top, bottom);
+        self.overdrawn = union(&self.overdrawn, rows);
+        self.mark_dirty(top, bottom);
+    }
+
+    fn clip_rows(&self, top: i32, bottom: i32) -> Range<usize> {
+        let height = self.size.height as i32;
+        top.clamp(0, height) as usize..bottom.clamp(0, height) as usize
+    }
+
+    /// Draws text over the current frame with its top-left corner at (x, y), clipped to the
+    /// screen. Call after `render`, which would paint over it.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: u32, scale: usize) {
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+        Canvas::new(&mut self.pixels, width, height).draw_text(x, y, text, color, scale);
+        let (_, text_height) = font::text_size(text, scale.max(1));
+        self.mark_drawn_over(y, y + text_height as i32);
+    }
+
+    /// Fills a rectangle of the current frame with a solid color, clipped to the screen. Call
+    /// after `render`, like `draw_text`.
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u32) {
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+        Canvas::new(&mut self.pixels, width, height).fill_rect(x, y, w, h, color);
+        self.mark_drawn_over(y, y + h);
+    }
+
+    /// Adds to how far the player has walked, in world units, which the weapon sways with.
+    pub fn walk(&mut self, distance: f32) {
+        self.walked = (self.walked + distance) % (std::f32::consts::TAU / WEAPON_BOB_RATE);
+    }
+
+    /// Draws the weapon over the bottom center of the screen, swaying side to side and
+    /// dipping at each step as the player walks.
+    fn render_weapon(&mut self) {
+        let Some(texture) = &self.weapon_texture else {
+            return;
+        };
         let (width, height) = (self.size.width as usize, self.size.height as usize);
-        for x in 0..width {
-            let hit = self.raycast(x);
+        let scale = (height as f32 * WEAPON_SCREEN_HEIGHT / texture.height() as f32)
+            .round()
+            .max(1.) as usize;
+        let phase = self.walked * WEAPON_BOB_RATE;
+        let bob = WEAPON_BOB * height as f32;
+        // It only ever dips, so its bottom edge never lifts off the screen's
+        let x = (width as f32 - (texture.width() * scale) as f32) / 2. + bob * phase.cos();
+        let y = height as f32 - (texture.height() * scale) as f32 + bob * phase.sin().abs();
+        Canvas::new(&mut self.pixels, width, height).blit(x as i32, y as i32, texture, scale);
+    }
 
-            let color = Self::material_to_color(hit.material, hit.side);
+    /// Draws a plus at the screen center, picking black or white per pixel so it contrasts
+    /// with whatever is behind it.
+    fn render_crosshair(&mut self) {
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+        let mut canvas = Canvas::new(&mut self.pixels, width, height);
+        let (cx, cy) = (width as i32 / 2, height as i32 / 2);
+        let arms = (1..=CROSSHAIR_SIZE * self.supersample as i32)
+            .flat_map(|i| [(cx + i, cy), (cx - i, cy), (cx, cy + i), (cx, cy - i)]);
+        for (x, y) in std::iter::once((cx, cy)).chain(arms) {
+            let Some(under) = canvas.pixel(x, y) else {
+                continue;
+            };
+            let [r, g, b, _] = under.to_le_bytes();
+            let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+            let color = if luma < 128 {
+                CROSSHAIR_LIGHT_COLOR
+            } else {
+                CROSSHAIR_DARK_COLOR
+            };
+            canvas.put_pixel(x, y, color);
+        }
+    }
 
-            let h = (height as f32 / hit.dist) as usize;
-            let y0 = usize::max((height / 2) - (h / 2), 0);
-            let y1 = usize::min((height / 2) + (h / 2), height - 1);
+    /// Draws a top-down view of the map in the corner, over the finished 3D frame.
+    fn render_minimap(&mut self) {
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+        let camera = &self.view;
+        let map = self.map.borrow();
+        let palette = &self.palette;
+        // Sized in screen pixels, so it's scaled up with the buffer when supersampling
+        let (cell, margin, player_size) = {
+            let factor = self.supersample as i32;
+            (
+                MINIMAP_CELL * factor,
+                MINIMAP_MARGIN * factor,
+                MINIMAP_PLAYER_SIZE * factor,
+            )
+        };
+        let mut canvas = Canvas::new(&mut self.pixels, width, height);
 
-            self.write_column(x, 0, y0, 0xFF202020);
-            self.write_column(x, y0, y1, color);
-            self.write_column(x, y1, height, 0xFF404040);
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                let color = match map.get(x, y) {
+                    Some(0) | None => MINIMAP_EMPTY_COLOR,
+                    Some(tile) => palette.get(map.texture_tile(tile)) | 0xFF000000,
+                };
+                canvas.fill_rect(
+                    margin + x as i32 * cell,
+                    margin + y as i32 * cell,
+                    cell,
+                    cell,
+                    color,
+                );
+            }
+        }
+
+        let to_minimap = |p: Vector2<f32>| {
+            (
+                margin as f32 + p.x * cell as f32,
+                margin as f32 + p.y * cell as f32,
+            )
+        };
+        let player = to_minimap(camera.player_pos);
+        let facing =
+            to_minimap(camera.player_pos + camera.facing_dir.normalize() * MINIMAP_FACING_LENGTH);
+        if self.show_rays {
+            for &point in self.ray_hits.iter().flatten() {
+                canvas.draw_line(player, to_minimap(point), DEBUG_RAY_COLOR);
+            }
+        }
+        canvas.draw_line(player, facing, MINIMAP_FACING_COLOR);
+        canvas.fill_rect(
+            player.0 as i32 - player_size / 2,
+            player.1 as i32 - player_size / 2,
+            player_size,
+            player_size,
+            MINIMAP_PLAYER_COLOR,
+        );
+    }
+
+    /// Draws the walls, floor and ceiling, filling the depth buffer as it goes.
+    fn render_world(&mut self) {
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+        let projection = self.projection();
+        let camera = &self.view;
+        let map = self.map.borrow();
+        let map: &Map = &map;
+        // The debug modes draw as if there were no textures at all, and depth mode unlit
+        let textured = self.render_mode == RenderMode::Textured;
+        let wireframe = self.render_mode == RenderMode::Wireframe;
+        let depth_mode = self.render_mode == RenderMode::Depth;
+        let light_map = self.light_map.as_ref().filter(|_| !depth_mode);
+        let no_textures = HashMap::new();
+        let textures = if textured {
+            &self.textures
+        } else {
+            &no_textures
+        };
+        let palette = &self.palette;
+        let horizon = camera.horizon(self.size.height);
+        let eye_height = camera.eye_height;
+        let fixed_point = self.fixed_point;
+        // Ray distances are in tiles
+        let max_dist = self.config.max_view_distance / map.world_scale();
+
+        // Columns are independent, so cast every ray in parallel before drawing anything
+        let slice = |hit: RayHit| {
+            let tile_height = map.tile_height(hit.material);
+            let light = light_map.map_or([1.; 3], |lights| lights.sample(hit.point));
+            let texture = if textured {
+                map.texture_tile(map.face_tile(hit.material, hit.face))
+            } else {
+                hit.material
+            };
+            let mut slice = WallSlice::new(
+                hit,
+                projection,
+                horizon,
+                eye_height,
+                height,
+                tile_height,
+                light,
+            )
+            .with_textures(texture, textures, palette);
+            slice.fade = (lighting::view_fade(hit.dist, max_dist) * 255.).round() as u8;
+            if wireframe && (hit.wall_x < WIREFRAME_EDGE || hit.wall_x > 1. - WIREFRAME_EDGE) {
+                slice.flat = slice
+                    .flat
+                    .map(|color| lighting::tint(color, [WIREFRAME_EDGE_SHADE; 3]));
+            }
+            if depth_mode {
+                slice.flat = Some(Self::depth_color(hit.dist));
+                slice.fade = 255;
+            }
+            slice
+        };
+        // Each ray covers `density` columns and is cast through the middle of them, so the
+        // rays still span the screen edge to edge and the leftmost and rightmost are mirror
+        // images. Half columns are counted in whole numbers for the fixed-point caster, which
+        // with a density of 1 lands on the column's center.
+        let density = self.ray_density;
+        let key = self.ray_key(map);
+        // Nothing a ray hits has changed if the camera and map haven't, so a still frame
+        // reuses the last one's rays and only shades them again
+        let cached = self
+            .ray_cache
+            .take()
+            .filter(|cache| self.ray_caching && cache.key == key);
+        let reused = cached.is_some();
+        let hits = cached.map_or_else(
+            || {
+                (0..width.div_ceil(density))
+                    .into_par_iter()
+                    .map(|ray| {
+                        let mut see_through = Vec::new();
+                        let half_columns = 2 * ray * density + density;
+                        let hit = if fixed_point {
+                            Self::raycast_fixed(
+                                camera,
+                                map,
+                                half_columns,
+                                2 * width,
+                                max_dist,
+                                &mut see_through,
+                            )
+                        } else {
+                            let xcam = Self::xcam(ray, density, width);
+                            let ray = Vector2::new(
+                                camera.facing_dir.x + camera.view_plane.x * xcam,
+                                camera.facing_dir.y + camera.view_plane.y * xcam,
+                            );
+                            cast_ray(map, camera.player_pos, ray, max_dist, &mut see_through)
+                        };
+                        (hit, see_through)
+                    })
+                    .collect()
+            },
+            |cache| cache.hits,
+        );
+        let (slices, glass): (Vec<WallSlice>, Vec<Vec<WallSlice>>) = hits
+            .par_iter()
+            .map(|(hit, see_through)| {
+                (
+                    slice(*hit),
+                    see_through
+                        .iter()
+                        .filter(|_| !depth_mode)
+                        .map(|&hit| slice(hit))
+                        .collect(),
+                )
+            })
+            .unzip();
+        if self.ray_caching {
+            self.ray_cache = Some(RayCache { key, hits });
+        }
+        let mut stats = RenderStats {
+            rays: slices.len(),
+            cached: reused,
+            ..RenderStats::default()
+        };
+        for slice in &slices {
+            stats.dda_steps += slice.hit.steps;
+            stats.max_steps = stats.max_steps.max(slice.hit.steps);
+            stats.rays_exited += (slice.hit.material == 0) as usize;
+        }
+        self.stats = stats;
+        let (slices, glass) = if density == 1 {
+            (slices, glass)
+        } else {
+            (0..width)
+                .map(|x| (slices[x / density].clone(), glass[x / density].clone()))
+                .unzip()
+        };
+        for (depth, slice) in self.depth.iter_mut().zip(&slices) {
+            *depth = match slice.hit.material {
+                0 => f32::INFINITY,
+                _ => slice.hit.dist,
+            };
+        }
+        self.ray_hits = DEBUG_RAY_COLUMNS
+            .iter()
+            .map(|&fraction| {
+                let hit = slices[((fraction * width as f32) as usize).min(width - 1)].hit;
+                (hit.material != 0).then_some(hit.point)
+            })
+            .collect();
+
+        // Then paint whole rows in parallel: each row is the floor or ceiling at one distance,
+        // with the wall slices that cross it drawn over the top
+        let (pos, dir, view_plane) = (camera.player_pos, camera.facing_dir, camera.view_plane);
+        // Through the middle of each pixel's column, like the walls' rays
+        let column_ray = |x: usize| dir + view_plane * Self::xcam(x, 1, width);
+        let (floor_texture, ceiling_texture) = (
+            self.floor_texture.as_ref().filter(|_| textured),
+            self.ceiling_texture.as_ref().filter(|_| textured),
+        );
+        let config = self.config;
+        let (has_liquids, time) = (map.has_liquids(), map.time());
+
+        // The sky is looked up by each ray's world angle, so it stays put as the player
+        // moves and turns, and half a screen above the horizon covers the sky's full height
+        let sky = self
+            .sky_texture
+            .as_ref()
+            .filter(|_| self.show_sky && textured);
+        let sky_top = (horizon - (height / 2) as f32) as isize;
+        let sky_columns: Vec<usize> = match sky {
+            Some(sky) => (0..width)
+                .map(|x| {
+                    let ray = column_ray(x);
+                    let u = ray.y.atan2(ray.x) / std::f32::consts::TAU;
+                    sky.column(u.rem_euclid(1.))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        self.pixels
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let above_horizon = y as f32 + 0.5 < horizon;
+                if let (Some(sky), true) = (sky, above_horizon) {
+                    // Looking far enough up runs off the top of the sky, which is stretched
+                    let sky_y = (y as isize - sky_top).max(0) as usize;
+                    let tex_y = (sky_y * sky.height() / (height / 2).max(1)).min(sky.height() - 1);
+                    let columns = row.iter_mut().zip(&slices).zip(&glass).zip(&sky_columns);
+                    for (((pixel, slice), glass), &column) in columns {
+                        let color = if (slice.y0..slice.y1).contains(&y) {
+                            slice.color_over(textures, y, || sky.texel(column, tex_y))
+                        } else {
+                            sky.texel(column, tex_y)
+                        };
+                        *pixel = Self::blend_glass(color, glass, textures, y);
+                    }
+                    return;
+                }
+
+                // The floor is eye_height below the eye and the ceiling a unit above the floor,
+                // so each row's distance is how far the plane is from eye level over how far
+                // the row is from the horizon
+                let (plane_dist, rows_from_horizon, plane_texture, plane_color) = if above_horizon {
+                    (
+                        1. - eye_height,
+                        horizon - (y as f32 + 0.5),
+                        ceiling_texture,
+                        config.ceiling_color,
+                    )
+                } else {
+                    (
+                        eye_height,
+                        y as f32 + 0.5 - horizon,
+                        floor_texture,
+                        config.floor_color,
+                    )
+                };
+
+                // Every pixel in a row is the same distance away
+                let plane_color = if depth_mode {
+                    Self::depth_color(plane_dist * projection / rows_from_horizon)
+                } else {
+                    plane_color
+                };
+
+                // Only the floor can hold liquids, which need each pixel's tile looking up
+                let liquids = (has_liquids && !above_horizon && !depth_mode).then_some(map);
+
+                // An untextured, unlit plane is one color across the row, so fill it in one
+                // go and only touch the pixels the walls and glass cover
+                if plane_texture.is_none() && light_map.is_none() && liquids.is_none() {
+                    row.fill(plane_color);
+                    for ((pixel, slice), glass) in row.iter_mut().zip(&slices).zip(&glass) {
+                        if (slice.y0..slice.y1).contains(&y) {
+                            *pixel = slice.color_over(textures, y, || plane_color);
+                        }
+                        if !glass.is_empty() {
+                            *pixel = Self::blend_glass(*pixel, glass, textures, y);
+                        }
+                    }
+                    return;
+                }
+
+                let row_dist = plane_dist * projection / rows_from_horizon;
+                let step = view_plane * (2. * row_dist / width as f32);
+                let mut floor = pos + column_ray(0) * row_dist;
+
+                let plane = |floor: Vector2<f32>| {
+                    if let Some(liquid) = liquids.and_then(|map| map.liquid_at(floor)) {
+                        // Liquids light themselves, so lava glows even in the dark
+                        let brightness = (liquid.brightness(floor, time) * 256.) as u32;
+                        return lighting::tint(liquid.color, [brightness; 3]);
+                    }
+                    let color = match plane_texture {
+                        Some(texture) => {
+                            let (u, v) = (floor.x - floor.x.floor(), floor.y - floor.y.floor());
+                            texture.sample(u, v)
+                        }
+                        None => plane_color,
+                    };
+                    match light_map {
+                        Some(lights) => {
+                            lighting::tint(color, Self::shade(1., lights.sample(floor)))
+                        }
+                        None => color,
+                    }
+                };
+                for ((pixel, slice), glass) in row.iter_mut().zip(&slices).zip(&glass) {
+                    let color = if (slice.y0..slice.y1).contains(&y) {
+                        slice.color_over(textures, y, || plane(floor))
+                    } else {
+                        plane(floor)
+                    };
+                    *pixel = Self::blend_glass(color, glass, textures, y);
+                    floor += step;
+                }
+            });
+        self.glass = glass;
+    }
+
+    /// Blends the see-through slices covering row `y` of a column over its color, farthest first.
+    fn blend_glass(
+        color: u32,
+        glass: &[WallSlice],
+        textures: &HashMap<u8, Texture>,
+        y: usize,
+    ) -> u32 {
+        glass
+            .iter()
+            .rev()
+            .filter(|slice| (slice.y0..slice.y1).contains(&y))
+            .fold(color, |color, slice| {
+                canvas::blend(color, slice.color(textures, y))
+            })
+    }
+
+    /// Draws sprites back to front as billboards, clipped per column against the wall depth.
+    fn render_sprites(&mut self) {
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+        let projection = self.projection();
+        let camera = &self.view;
+        let horizon = camera.horizon(self.size.height);
+        let light_map = self.light_map.as_ref();
+        let world_scale = self.map.borrow().world_scale();
+        let max_dist = self.config.max_view_distance / world_scale;
+        let lod_dist = self.sprite_lod_distance / world_scale;
+        let (dir, plane) = (camera.facing_dir, camera.view_plane);
+        // Inverse of the [view_plane | facing_dir] camera matrix
+        let inv_det = 1. / (plane.x * dir.y - dir.x * plane.y);
+
+        let mut order: Vec<(f32, usize)> = self
+            .sprites
+            .iter()
+            .enumerate()
+            .map(|(i, sprite)| ((sprite.position - camera.player_pos).magnitude2(), i))
+            .collect();
+        order.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        for (_, i) in order {
+            let sprite = &self.sprites[i];
+            let Some(texture) = self.sprite_textures.get(sprite.texture) else {
+                continue;
+            };
+            let rel = sprite.position - camera.player_pos;
+            let cam_x = inv_det * (dir.y * rel.x - dir.x * rel.y);
+            let cam_depth = inv_det * (-plane.y * rel.x + plane.x * rel.y);
+            if cam_depth < SPRITE_NEAR_CLIP {
+                continue;
+            }
+
+            let screen_x = (width as f32 / 2.) * (1. + cam_x / cam_depth);
+            let size = projection / cam_depth;
+            let left = screen_x - size / 2.;
+            // Sprites are a unit tall, standing on the floor
+            let top = horizon + size * (camera.eye_height - 1.);
+            let light = light_map.map_or([1.; 3], |lights| lights.sample(sprite.position));
+            let shade = Self::shade(lighting::fog(cam_depth), light);
+            let fade = (lighting::view_fade(cam_depth, max_dist) * 255.).round() as u32;
+            if fade == 0 {
+                continue;
+            }
+            // Depth mode draws the sprite's silhouette at its depth
+            let depth_color =
+                (self.render_mode == RenderMode::Depth).then(|| Self::depth_color(cam_depth));
+            // How far its colors have faded into their average, 255 being all the way, when
+            // it's drawn as a block
+            let lod = if lod_dist.is_finite() {
+                let start = lod_dist * (1. - SPRITE_LOD_FADE);
+                let lod = ((cam_depth - start) / (lod_dist - start)).clamp(0., 1.);
+                (lod * 255.).round() as u8
+            } else {
+                0
+            };
+            let average = texture.average();
+            let shaded = |texel: u32| match depth_color {
+                Some(depth_color) => (depth_color, 0xFF),
+                None => (
+                    lighting::tint(texel, shade) | 0xFF000000,
+                    (texel >> 24) * fade / 255,
+                ),
+            };
+            // A block is one color all over, so it's only shaded once
+            let block = (lod == 255).then(|| shaded(average));
+            let (u, v) = match block {
+                Some(_) => texture.opaque_bounds(),
+                None => (0. ..1., 0. ..1.),
+            };
+            let (x0, x1) = (
+                (left + u.start * size).max(0.) as usize,
+                ((left + u.end * size).max(0.) as usize).min(width),
+            );
+            let (y0, y1) = (
+                (top + v.start * size).max(0.) as usize,
+                ((top + v.end * size).max(0.) as usize).min(height),
+            );
+
+            for x in x0..x1 {
+                if cam_depth >= self.depth[x] {
+                    continue;
+                }
+                let tex_x = texture.column((x as f32 + 0.5 - left) / size);
+                // Panes in front of the sprite are blended back over it
+                let glass = &self.glass[x];
+                let glass = &glass[..glass.partition_point(|pane| pane.hit.dist < cam_depth)];
+                for y in y0..y1 {
+                    let (color, alpha) = match block {
+                        Some(block) => block,
+                        None => {
+                            let v = (y as f32 + 0.5 - top) / size;
+                            let tex_y =
+                                ((v * texture.height() as f32) as usize).min(texture.height() - 1);
+                            let texel = texture.texel(tex_x, tex_y);
+                            // Fading the color alone, so the shape doesn't change until
+                            // it's a block
+                            let texel = match lod {
+                                0 => texel,
+                                _ => {
+                                    canvas::mix(texel, average & 0xFFFFFF | texel & !0xFFFFFF, lod)
+                                }
+                            };
+                            if texel >> 24 == 0 {
+                                continue;
+                            }
+                            shaded(texel)
+                        }
+                    };
+                    // The pixel behind already has the panes over it, so they go over the
+                    // sprite before its alpha, from soft edges and the fade, mixes the two
+                    let color = Self::blend_glass(color, glass, &self.textures, y);
+                    canvas::blend_pixel(
+                        &mut self.pixels[y * width + x],
+                        color & 0x00FFFFFF | alpha << 24,
+                    );
+                }
+            }
         }
     }
 
+    /// Renders one frame from the given viewpoint entirely on the CPU and returns its RGBA bytes.
+    /// The direction and plane may be any length; they're rescaled together so the direction
+    /// is a unit vector, which keeps the field of view.
+    pub fn render_to_vec(
+        &mut self,
+        player_pos: Vector2<f32>,
+        facing_dir: Vector2<f32>,
+        view_plane: Vector2<f32>,
+    ) -> &[u8] {
+        let scale = facing_dir.magnitude().recip();
+        self.render_from(Camera {
+            player_pos,
+            facing_dir: facing_dir * scale,
+            view_plane: view_plane * scale,
+            pitch: 0.,
+            eye_height: 0.5,
+        });
+        self.pixels()
+    }
+
+    /// Writes the last rendered frame to a PNG at the render resolution.
+    pub fn save_screenshot(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        // Each u32 is stored little-endian with red in the low byte, so the bytes are already RGBA
+        image::save_buffer(
+            path,
+            self.pixels(),
+            self.size.width,
+            self.size.height,
+            image::ExtendedColorType::Rgba8,
+        )
+        .with_context(|| format!("failed to save screenshot {}", path.display()))
+    }
+
     pub fn pixels(&self) -> &[u8] {
         bytemuck::cast_slice::<u32, u8>(&self.pixels)
     }
 }
+
+/// The smallest range covering both `a` and `b`, ignoring either if it's empty.
+fn union(a: &Range<usize>, b: Range<usize>) -> Range<usize> {
+    if a.is_empty() {
+        b
+    } else if b.is_empty() {
+        a.clone()
+    } else {
+        a.start.min(b.start)..a.end.max(b.end)
+    }
+}
+
+/// Finds `path` under the directory the executable is in, if it was shipped with its
+/// assets, or else under the source tree it was built from. Either way the game runs from
+/// any working directory.
+fn asset_path(path: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(path)))
+        .filter(|shipped| shipped.exists())
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Light;
+
+    const HEIGHT: u32 = 48;
+
+    /// A room 5 tiles across and 3 deep, inside.
+    const ROOM: &str = "1111111\n1000001\n1000001\n1000001\n1111111";
+    /// A walled-in map with pillars and corners to see from every side.
+    const MAZE: &str = "11111111\n10000101\n10110001\n10000011\n11111111";
+
+    /// An untextured renderer of `map` from `position`, facing `direction` with a 90 degree
+    /// field of view. The minimap and crosshair are hidden so they don't cover any columns.
+    fn renderer(map: Map, position: [f32; 2], direction: [f32; 2], width: u32) -> Renderer {
+        let camera = Camera::new(position.into(), direction.into(), 1.);
+        let size = PhysicalSize::new(width, HEIGHT);
+        let mut renderer = Renderer::untextured(
+            Rc::new(RefCell::new(camera)),
+            Rc::new(RefCell::new(map)),
+            size,
+        );
+        renderer.set_show_minimap(false);
+        renderer.set_show_crosshair(false);
+        renderer
+    }
+
+    #[test]
+    fn large_map_walls_are_where_the_map_puts_them() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/maps/large.map");
+        // From the spawn: a closed door through the middle of (20, 17), the x = 0 border, the
+        // wall along y = 8 and the y = 23 border
+        let views = [
+            ([1., 0.], 3.),
+            ([-1., 0.], 16.5),
+            ([0., -1.], 8.5),
+            ([0., 1.], 5.5),
+        ];
+        for (direction, expected) in views {
+            // An odd width puts the middle column's ray straight ahead
+            let mut renderer = renderer(Map::load(path).unwrap(), [17.5, 17.5], direction, 65);
+            renderer.render();
+            let depth = renderer.depth()[32];
+            assert!((depth - expected).abs() < 1e-3, "{direction:?}: {depth}");
+        }
+    }
+
+    #[test]
+    fn resizing_reallocates_the_buffers() {
+        let mut renderer = renderer(Map::default(), [5.5, 5.5], [1., 0.3], 256);
+        renderer.resize(PhysicalSize::new(256, 192));
+        renderer.render();
+        let full = renderer.pixels().to_vec();
+
+        // A quarter of the resolution, as the lowest render scale gives
+        renderer.resize(PhysicalSize::new(64, 48));
+        assert_eq!(renderer.pixels().len(), 64 * 48 * 4);
+        assert_eq!(renderer.depth().len(), 64);
+        renderer.render();
+
+        // Scaling back up draws the same frame as before
+        renderer.resize(PhysicalSize::new(256, 192));
+        assert_eq!(renderer.pixels().len(), 256 * 192 * 4);
+        assert_eq!(renderer.depth().len(), 256);
+        renderer.render();
+        assert!(renderer.pixels() == full);
+    }
+
+    #[test]
+    fn supersampled_frames_are_averaged_down_to_the_output_size() {
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [1.5, 2.5], [1., 0.], 64);
+        renderer.set_supersample(2);
+        assert_eq!(renderer.output_size(), PhysicalSize::new(64, HEIGHT));
+        assert_eq!(renderer.size(), PhysicalSize::new(128, 2 * HEIGHT));
+        renderer.render();
+        assert_eq!(renderer.pixels().len(), 4 * 64 * HEIGHT as usize * 4);
+
+        // Every 2x2 block of a black and white checkerboard averages to mid-gray
+        for (i, pixel) in renderer.pixels.iter_mut().enumerate() {
+            let (x, y) = (i % 128, i / 128);
+            *pixel = if (x + y) % 2 == 0 {
+                0xFFFFFFFF
+            } else {
+                0xFF000000
+            };
+        }
+        let mut out = vec![0; 64 * HEIGHT as usize * 4];
+        renderer.downsample(0..HEIGHT as usize, &mut out);
+        assert!(out.chunks(4).all(|pixel| pixel == [0x80, 0x80, 0x80, 0xFF]));
+    }
+
+    #[test]
+    fn frames_drawn_in_parallel_match_ones_drawn_on_one_thread() {
+        let frame = |threads: usize, fixed_point: bool| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            // Made inside the pool, as it can't be sent to it
+            pool.install(|| {
+                let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/maps/large.map");
+                let map = Rc::new(RefCell::new(Map::load(path).unwrap()));
+                let camera = Camera::new([17.5, 17.5].into(), [0.3, -1.].into(), 0.8);
+                let size = PhysicalSize::new(320, 200);
+                let mut renderer = Renderer::new(Rc::new(RefCell::new(camera)), map, size).unwrap();
+                renderer.set_fixed_point(fixed_point);
+                renderer.render();
+                renderer.pixels().to_vec()
+            })
+        };
+        for fixed_point in [false, true] {
+            assert!(
+                frame(1, fixed_point) == frame(4, fixed_point),
+                "{fixed_point}"
+            );
+        }
+    }
+
+    #[test]
+    fn depth_buffer_holds_each_columns_wall_distance() {
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [1.5, 2.5], [1., 0.], 64);
+        renderer.render();
+        assert_eq!(renderer.depth().len(), 64);
+        for (x, &depth) in renderer.depth().iter().enumerate() {
+            // Rays reach the far wall 4.5 ahead unless they first cross one of the side
+            // walls, 1.5 to either side
+            let xcam = (2 * x + 1) as f32 / 64. - 1.;
+            let expected = (1.5 / xcam.abs()).min(4.5);
+            assert!(
+                (depth - expected).abs() < 1e-3,
+                "column {x}: {depth} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn render_to_vec_leaves_the_shared_camera_alone() {
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [4.5, 2.5], [-1., 0.], 64);
+        let shared = renderer.camera();
+        // Twice the usual length, which is scaled away
+        let frame = renderer
+            .render_to_vec([1.5, 2.5].into(), [2., 0.].into(), [0., -2.].into())
+            .to_vec();
+        assert_eq!(renderer.camera(), shared);
+
+        let mut expected = self::renderer(Map::parse(ROOM).unwrap(), [1.5, 2.5], [1., 0.], 64);
+        expected.render();
+        assert!(frame == expected.pixels());
+
+        // Down the middle column: ceiling, the far wall 4.5 away, then floor
+        let pixel = |y: u32| {
+            let i = ((y * 64 + 32) * 4) as usize;
+            u32::from_le_bytes(frame[i..i + 4].try_into().unwrap())
+        };
+        let (ceiling, wall, floor) = (pixel(0), pixel(HEIGHT / 2), pixel(HEIGHT - 1));
+        assert_eq!(ceiling, renderer.config().ceiling_color);
+        assert_eq!(floor, renderer.config().floor_color);
+        assert!(wall != ceiling && wall != floor);
+    }
+
+    /// `map` reflected left to right, or top to bottom if `vertical`.
+    fn mirror(map: &str, vertical: bool) -> String {
+        let rows: Vec<String> = if vertical {
+            map.lines().rev().map(str::to_owned).collect()
+        } else {
+            map.lines().map(|row| row.chars().rev().collect()).collect()
+        };
+        rows.join("\n")
+    }
+
+    #[test]
+    fn mirrored_scenes_have_mirrored_depth() {
+        let map = MAZE;
+        let (width, height) = (8., 5.);
+        // Off the grid and facing up and right, so every ray starts partway into its cell
+        let (position, direction) = ([2.3, 3.4], [1., -0.3]);
+        for fixed_point in [false, true] {
+            let mut original = renderer(Map::parse(map).unwrap(), position, direction, 64);
+            original.set_fixed_point(fixed_point);
+            original.render();
+            // Reflecting the scene flips the camera's handedness, reversing the columns
+            for vertical in [false, true] {
+                let (position, direction) = if vertical {
+                    (
+                        [position[0], height - position[1]],
+                        [direction[0], -direction[1]],
+                    )
+                } else {
+                    (
+                        [width - position[0], position[1]],
+                        [-direction[0], direction[1]],
+                    )
+                };
+                let mirrored = Map::parse(&mirror(map, vertical)).unwrap();
+                let mut mirrored = renderer(mirrored, position, direction, 64);
+                mirrored.set_fixed_point(fixed_point);
+                mirrored.render();
+                let reversed = mirrored.depth().iter().rev();
+                for (x, (&depth, &expected)) in reversed.zip(original.depth()).enumerate() {
+                    assert!(
+                        (depth - expected).abs() < 1e-3,
+                        "fixed {fixed_point}, vertical {vertical}, column {x}: {depth}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// The rows of each column from the first that isn't ceiling to the last that isn't
+    /// floor, which on an untextured map without lights is where its wall is drawn.
+    fn wall_spans(renderer: &Renderer) -> Vec<Range<usize>> {
+        let (width, height) = (
+            renderer.size().width as usize,
+            renderer.size().height as usize,
+        );
+        let config = renderer.config();
+        let pixels: &[u32] = bytemuck::cast_slice(renderer.pixels());
+        (0..width)
+            .map(|x| {
+                let column = || (0..height).map(|y| pixels[y * width + x]);
+                let top = column().position(|pixel| pixel != config.ceiling_color);
+                let bottom = column().rposition(|pixel| pixel != config.floor_color);
+                match (top, bottom) {
+                    (Some(top), Some(bottom)) if top <= bottom => top..bottom + 1,
+                    _ => 0..0,
+                }
+            })
+            .collect()
+    }
+
+    fn wall_heights(renderer: &Renderer) -> Vec<usize> {
+        wall_spans(renderer)
+            .into_iter()
+            .map(|span| span.len())
+            .collect()
+    }
+
+    #[test]
+    fn column_heights_hold_across_frames_and_resizes() {
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [2.3, 1.8], [1., 0.4], 64);
+        // Cast every frame's rays afresh rather than reusing the last
+        renderer.set_ray_caching(false);
+        renderer.render();
+        let heights = wall_heights(&renderer);
+        assert!(heights.iter().all(|&height| height > 0));
+
+        renderer.render();
+        assert_eq!(wall_heights(&renderer), heights);
+
+        renderer.resize(PhysicalSize::new(128, 96));
+        renderer.render();
+        renderer.resize(PhysicalSize::new(64, HEIGHT));
+        renderer.render();
+        assert_eq!(wall_heights(&renderer), heights);
+    }
+
+    #[test]
+    fn flat_walls_are_equally_tall_in_every_column() {
+        // A corridor 3 tiles deep and long enough that every ray reaches the far wall
+        let map = format!("11111\n{}11111", "10001\n".repeat(29));
+        for fixed_point in [false, true] {
+            // Facing the wall along x = 4 straight on, 2.5 tiles away
+            let mut renderer = renderer(Map::parse(&map).unwrap(), [1.5, 15.5], [1., 0.], 64);
+            renderer.set_fixed_point(fixed_point);
+            renderer.render();
+            assert!(renderer
+                .depth()
+                .iter()
+                .all(|&depth| (depth - 2.5).abs() < 1e-3));
+            let heights = wall_heights(&renderer);
+            assert!(heights[0] > 0);
+            assert!(
+                heights.iter().all(|&height| height == heights[0]),
+                "{heights:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn adding_a_light_relights_the_next_frame() {
+        let map = Rc::new(RefCell::new(Map::parse(ROOM).unwrap()));
+        let camera = Camera::new([1.5, 2.5].into(), [1., 0.].into(), 1.);
+        let size = PhysicalSize::new(64, HEIGHT);
+        let mut renderer = Renderer::untextured(Rc::new(RefCell::new(camera)), map.clone(), size);
+        renderer.render();
+        let unlit = renderer.pixels().to_vec();
+        renderer.render();
+        assert!(renderer.pixels() == unlit);
+
+        map.borrow_mut().add_light(Light {
+            position: [4.5, 2.5].into(),
+            color: [1., 0.5, 0.],
+            radius: 3.,
+        });
+        renderer.render();
+        assert!(renderer.pixels() != unlit);
+    }
+
+    #[test]
+    fn dirty_rows_are_the_ones_drawn_to() {
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [1.5, 2.5], [1., 0.], 64);
+        assert_eq!(renderer.take_dirty_rows(), Some(0..HEIGHT as usize));
+        assert_eq!(renderer.take_dirty_rows(), None);
+
+        renderer.render();
+        assert_eq!(renderer.take_dirty_rows(), Some(0..HEIGHT as usize));
+        // Drawing the same frame again changes nothing
+        renderer.render();
+        assert_eq!(renderer.take_dirty_rows(), None);
+
+        // One line of text is a glyph tall, and rectangles are clipped to the screen
+        renderer.draw_text(4, 10, "FPS 60", 0xFFFFFFFF, 2);
+        assert_eq!(renderer.take_dirty_rows(), Some(10..24));
+        renderer.fill_rect(0, 40, 64, 20, 0xFF000000);
+        renderer.fill_rect(0, -5, 64, 7, 0xFF000000);
+        assert_eq!(renderer.take_dirty_rows(), Some(0..HEIGHT as usize));
+        renderer.fill_rect(0, 60, 64, 4, 0xFF000000);
+        assert_eq!(renderer.take_dirty_rows(), None);
+    }
+
+    #[test]
+    fn a_still_camera_only_dirties_the_hud() {
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [1.5, 2.5], [1., 0.], 64);
+        let frame = |renderer: &mut Renderer, fps: &str| {
+            renderer.render();
+            renderer.draw_text(4, 10, fps, 0xFFFFFFFF, 2);
+            renderer.take_dirty_rows()
+        };
+        assert_eq!(frame(&mut renderer, "FPS 60"), Some(0..HEIGHT as usize));
+        // The text is drawn over the same rows every frame, and the walls under it are put
+        // back before it is
+        assert_eq!(frame(&mut renderer, "FPS 59"), Some(10..24));
+        assert_eq!(frame(&mut renderer, "FPS 59"), Some(10..24));
+
+        // Turning moves wall edges below the text too
+        renderer.camera.borrow_mut().rotate(cgmath::Rad(0.1));
+        let rows = frame(&mut renderer, "FPS 59").unwrap();
+        assert!(rows.end > 24, "{rows:?}");
+    }
+
+    #[test]
+    fn a_frame_that_would_be_the_same_is_not_drawn_again() {
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [1.5, 2.5], [1., 0.], 64);
+        renderer.render();
+        assert!(!renderer.stats().frame_cached);
+        let frame = renderer.pixels.clone();
+
+        // Scribbled on where the renderer can't see, so only drawing the frame again would
+        // paint over it
+        const SCRIBBLE: u32 = 0xFF00FF00;
+        renderer.pixels[0] = SCRIBBLE;
+        renderer.render();
+        assert!(renderer.stats().frame_cached);
+        assert_eq!(renderer.pixels[0], SCRIBBLE);
+        assert!(renderer.pixels[1..] == frame[1..]);
+        // But rows drawn over through the renderer are put back
+        renderer.fill_rect(0, 30, 64, 4, SCRIBBLE);
+        renderer.render();
+        assert!(renderer.stats().frame_cached);
+        assert!(renderer.pixels[1..] == frame[1..]);
+
+        // Anything that changes the picture draws it again
+        let changes: [&dyn Fn(&mut Renderer); 5] = [
+            &|renderer| renderer.camera.borrow_mut().rotate(cgmath::Rad(0.1)),
+            &|renderer| renderer.set_render_mode(RenderMode::Depth),
+            &|renderer| renderer.set_tile_color(1, 0xFF808080),
+            &|renderer| {
+                renderer.map.borrow_mut().add_light(Light {
+                    position: [4.5, 2.5].into(),
+                    color: [1., 0.5, 0.],
+                    radius: 3.,
+                })
+            },
+            &|renderer| {
+                renderer.add_sprite(Sprite {
+                    position: [3.5, 2.5].into(),
+                    texture: 0,
+                })
+            },
+        ];
+        for (i, change) in changes.iter().enumerate() {
+            change(&mut renderer);
+            renderer.render();
+            assert!(!renderer.stats().frame_cached, "change {i}");
+            renderer.render();
+            assert!(renderer.stats().frame_cached, "change {i}");
+        }
+    }
+
+    #[test]
+    fn distant_sprites_fade_into_a_block_of_their_average_color() {
+        // Three tiles ahead, in front of the far wall
+        let mut renderer = renderer(Map::parse(ROOM).unwrap(), [1.5, 2.5], [1., 0.], 64);
+        renderer.add_sprite(Sprite {
+            position: [4.5, 2.5].into(),
+            texture: 0,
+        });
+        let checker = image::RgbaImage::from_fn(2, 2, |x, y| match (x + y) % 2 {
+            0 => image::Rgba([255, 0, 0, 255]),
+            _ => image::Rgba([0, 0, 255, 255]),
+        });
+        renderer.sprite_textures.push(Texture::from_image(&checker));
+        // How far apart the reddest and bluest pixels in the middle of the sprite are
+        let mut contrast = |lod_distance: f32| {
+            renderer.set_sprite_lod_distance(lod_distance);
+            renderer.render();
+            let pixels: &[u32] = bytemuck::cast_slice(renderer.pixels());
+            let reds = (20..28)
+                .flat_map(|y| (28..36).map(move |x| (y, x)))
+                .map(|(y, x)| pixels[y * 64 + x].to_le_bytes()[0]);
+            reds.clone().max().unwrap() - reds.min().unwrap()
+        };
+
+        let textured = contrast(f32::INFINITY);
+        assert!(textured > 100, "{textured}");
+        assert_eq!(contrast(10.), textured);
+        // Partway through fading into the block
+        let fading = contrast(3.3);
+        assert!(0 < fading && fading < textured, "{fading}");
+        assert_eq!(contrast(2.), 0);
+    }
+
+    #[test]
+    fn fixed_and_float_rays_draw_the_same_walls() {
+        let map = MAZE;
+        let views = [
+            ([2.3, 3.4], [1., -0.3]),
+            ([6.6, 1.2], [-1., 0.8]),
+            ([4.5, 1.5], [0., 1.]),
+            ([1.1, 1.9], [0.2, 1.]),
+        ];
+        for (position, direction) in views {
+            let spans = [false, true].map(|fixed_point| {
+                let mut renderer = renderer(Map::parse(map).unwrap(), position, direction, 64);
+                renderer.set_fixed_point(fixed_point);
+                renderer.render();
+                wall_spans(&renderer)
+            });
+            for (x, (float, fixed)) in spans[0].iter().zip(&spans[1]).enumerate() {
+                assert!(!float.is_empty(), "{position:?}, column {x}");
+                assert!(
+                    float.start.abs_diff(fixed.start) <= 1 && float.end.abs_diff(fixed.end) <= 1,
+                    "{position:?}, column {x}: {float:?} != {fixed:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rays_are_cast_through_the_middle_of_their_columns() {
+        for density in [1, 2] {
+            for rays in [65, 641] {
+                let width = rays * density;
+                // With an odd number of rays the middle one looks straight ahead
+                let middle = Renderer::xcam(rays / 2, density, width);
+                assert!(middle.abs() < 1e-6, "{density}, {width}: {middle}");
+                // and the end ones are half a ray in from the edges
+                let end = 1. - 1. / rays as f32;
+                let left = Renderer::xcam(0, density, width);
+                let right = Renderer::xcam(rays - 1, density, width);
+                assert!((left + end).abs() < 1e-6, "{density}, {width}: {left}");
+                assert!((right - end).abs() < 1e-6, "{density}, {width}: {right}");
+            }
+        }
+        // Floor, ceiling and sky pixels are cast one per column, mirrored about the middle
+        for width in [64, 65, 640] {
+            for x in 0..width {
+                let (left, right) = (
+                    Renderer::xcam(x, 1, width),
+                    Renderer::xcam(width - 1 - x, 1, width),
+                );
+                assert!((left + right).abs() < 1e-6, "{width}, {x}: {left} {right}");
+            }
+        }
+    }
+
+    #[test]
+    fn rays_through_a_gap_in_the_border_see_nothing() {
+        // Open on the right, so rays leave the map there rather than hitting anything
+        let map = "11111\n10000\n10000\n10000\n11111";
+        for fixed_point in [false, true] {
+            let mut renderer = renderer(Map::parse(map).unwrap(), [1.5, 2.5], [1., 0.], 64);
+            renderer.set_fixed_point(fixed_point);
+            renderer.render();
+            let depth = renderer.depth();
+            assert!(
+                depth[31].is_infinite() && depth[32].is_infinite(),
+                "{depth:?}"
+            );
+            // The outermost columns still find the top and bottom walls
+            assert!(depth[0].is_finite() && depth[63].is_finite(), "{depth:?}");
+        }
+    }
+}
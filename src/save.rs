@@ -0,0 +1,85 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::Camera,
+    map::{Door, Map},
+};
+
+/// Starts every save file, so something else passed by mistake is refused up front.
+const SAVE_MAGIC: [u8; 4] = *b"RDSV";
+/// Written after the magic. Bump it whenever GameState's layout changes, and keep reading
+/// the old layouts in `GameState::from_bytes` so earlier saves still load.
+pub const SAVE_VERSION: u32 = 1;
+
+/// Everything needed to put the player back where they were: the camera, which level they
+/// were on and how far each of its doors was open.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameState {
+    pub camera: Camera,
+    pub level: usize,
+    pub doors: Vec<((usize, usize), Door)>,
+}
+
+impl GameState {
+    /// Snapshots the player and the doors of the map they're on, which is level `level`.
+    pub fn capture(camera: &Camera, map: &Map, level: usize) -> Self {
+        let mut doors: Vec<_> = map.doors().collect();
+        // Sorted so saving the same state twice writes the same bytes
+        doors.sort_by_key(|&(cell, _)| cell);
+        Self {
+            camera: *camera,
+            level,
+            doors,
+        }
+    }
+
+    /// Puts the camera and doors back. The map must be a fresh copy of level `self.level`;
+    /// doors the save doesn't mention are left as the map has them.
+    pub fn apply(&self, camera: &mut Camera, map: &mut Map) {
+        *camera = self.camera;
+        for &((x, y), door) in &self.doors {
+            if !map.set_door(x, y, door) {
+                log::warn!("saved door at ({x}, {y}) isn't in the map, skipping it");
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = SAVE_MAGIC.to_vec();
+        bytes.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self).context("failed to encode game state")?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let Some(rest) = bytes.strip_prefix(&SAVE_MAGIC) else {
+            bail!("not a save file");
+        };
+        let (version, body) = rest
+            .split_first_chunk::<4>()
+            .context("save file is truncated")?;
+        match u32::from_le_bytes(*version) {
+            SAVE_VERSION => bincode::deserialize(body).context("save file is corrupt"),
+            version if version > SAVE_VERSION => bail!(
+                "save file is version {version}, newer than the {SAVE_VERSION} this build reads"
+            ),
+            version => bail!("save file version {version} is no longer supported"),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()?)
+            .with_context(|| format!("failed to write save {}", path.display()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes =
+            fs::read(path).with_context(|| format!("failed to read save {}", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("failed to load save {}", path.display()))
+    }
+}
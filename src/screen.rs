@@ -0,0 +1,102 @@
+use std::ops::Range;
+
+use wgpu::{
+    Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureDescriptor, TextureFormat,
+    TextureUsages,
+};
+use winit::dpi::PhysicalSize;
+
+/// The GPU side of the CPU renderer: a texture its pixels are uploaded into each frame and
+/// the bind group the blit pipeline samples it through.
+pub struct Screen {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Screen {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let screen_descriptor = TextureDescriptor {
+            label: Some("screen"),
+            size: Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // The pixels are sRGB-encoded, so sampling decodes them to linear and the sRGB
+            // surface encodes them back, showing the bytes unchanged
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+        let texture = device.create_texture(&screen_descriptor);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+        Self {
+            texture,
+            bind_group,
+        }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Copies a full frame of tightly packed RGBA pixels into the texture.
+    pub fn upload(&self, queue: &wgpu::Queue, pixels: &[u8]) {
+        self.upload_rows(queue, pixels, 0..self.texture.height());
+    }
+
+    /// Copies only the given rows of a full frame of tightly packed RGBA pixels.
+    pub fn upload_rows(&self, queue: &wgpu::Queue, pixels: &[u8], rows: Range<u32>) {
+        let width = self.texture.width();
+        debug_assert_eq!(
+            pixels.len(),
+            (width * self.texture.height() * 4) as usize,
+            "frame doesn't match the screen texture size"
+        );
+        let texture = ImageCopyTexture {
+            texture: &self.texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: 0,
+                y: rows.start,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        };
+        let size = Extent3d {
+            width,
+            height: rows.len() as u32,
+            depth_or_array_layers: 1,
+        };
+        let data_layout = ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(width * 4),
+            rows_per_image: Some(rows.len() as u32),
+        };
+        let row_bytes = width as usize * 4;
+        let data = &pixels[rows.start as usize * row_bytes..rows.end as usize * row_bytes];
+        queue.write_texture(texture, data, data_layout, size);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{ops::Range, path::Path};
+
+use anyhow::{Context, Result};
+use image::RgbaImage;
+
+/// A decoded image stored in the same packed format as the screen buffer.
+pub struct Texture {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+    /// See `average` and `opaque_bounds`.
+    average: u32,
+    opaque_bounds: (Range<f32>, Range<f32>),
+    /// Copies of the pixels darkened evenly from black up to full brightness, like DOOM's
+    /// colormaps, so shading a texel is a lookup rather than a multiply. Empty until built.
+    shades: Vec<Vec<u32>>,
+}
+
+impl Texture {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("failed to load texture {}", path.display()))?
+            .into_rgba8();
+        Ok(Self::from_image(&image))
+    }
+
+    pub fn from_image(image: &RgbaImage) -> Self {
+        let (width, height) = image.dimensions();
+        let pixels: Vec<u32> = image
+            .pixels()
+            .map(|pixel| u32::from_le_bytes(pixel.0))
+            .collect();
+        Self {
+            width: width as usize,
+            height: height as usize,
+            average: average(&pixels),
+            opaque_bounds: opaque_bounds(&pixels, width as usize),
+            pixels,
+            shades: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Samples the texel at (u, v), with both coordinates wrapped into [0, 1).
+    pub fn sample(&self, u: f32, v: f32) -> u32 {
+        let x = (u.rem_euclid(1.) * self.width as f32) as usize;
+        let y = (v.rem_euclid(1.) * self.height as f32) as usize;
+        self.texel(x.min(self.width - 1), y.min(self.height - 1))
+    }
+
+    /// The texel column for a horizontal coordinate in [0, 1).
+    pub fn column(&self, u: f32) -> usize {
+        ((u * self.width as f32) as usize).min(self.width - 1)
+    }
+
+    pub fn texel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// The color of the texture seen from too far away to make out any of it: its texels'
+    /// colors averaged by how much each covers, fully opaque.
+    pub fn average(&self) -> u32 {
+        self.average
+    }
+
+    /// The smallest rectangle holding every texel that isn't fully transparent, as ranges of
+    /// u and v in [0, 1]. Both are empty if there are none.
+    pub fn opaque_bounds(&self) -> (Range<f32>, Range<f32>) {
+        self.opaque_bounds.clone()
+    }
+
+    /// Precomputes `levels` darkened copies, the first black and the last unchanged, with
+    /// `shade` darkening one texel to a brightness in [0, 1]. Fewer than 2 levels clears them.
+    pub fn build_shades(&mut self, levels: usize, shade: impl Fn(u32, f32) -> u32) {
+        self.shades = if levels < 2 {
+            Vec::new()
+        } else {
+            (0..levels)
+                .map(|level| {
+                    let brightness = level as f32 / (levels - 1) as f32;
+                    self.pixels
+                        .iter()
+                        .map(|&texel| shade(texel, brightness))
+                        .collect()
+                })
+                .collect()
+        };
+    }
+
+    /// How many shaded copies have been built, or 0 if none.
+    pub fn shade_levels(&self) -> usize {
+        self.shades.len()
+    }
+
+    /// The texel at (x, y) from the given shaded copy.
+    pub fn shaded_texel(&self, level: usize, x: usize, y: usize) -> u32 {
+        self.shades[level][y * self.width + x]
+    }
+}
+
+fn average(pixels: &[u32]) -> u32 {
+    let mut sums = [0u64; 4];
+    for pixel in pixels {
+        let [r, g, b, a] = pixel.to_le_bytes().map(u64::from);
+        for (sum, value) in sums.iter_mut().zip([r * a, g * a, b * a, a]) {
+            *sum += value;
+        }
+    }
+    let [r, g, b, a] = sums;
+    let color = |sum: u64| (sum / a.max(1)) as u8;
+    u32::from_le_bytes([color(r), color(g), color(b), 0xFF])
+}
+
+fn opaque_bounds(pixels: &[u32], width: usize) -> (Range<f32>, Range<f32>) {
+    let height = pixels.len() / width.max(1);
+    let opaque = |x: usize, y: usize| pixels[y * width + x] >> 24 != 0;
+    let columns: Vec<usize> = (0..width)
+        .filter(|&x| (0..height).any(|y| opaque(x, y)))
+        .collect();
+    let rows: Vec<usize> = (0..height)
+        .filter(|&y| (0..width).any(|x| opaque(x, y)))
+        .collect();
+    let bounds = |cells: &[usize], size: usize| match (cells.first(), cells.last()) {
+        (Some(&first), Some(&last)) => first as f32 / size as f32..(last + 1) as f32 / size as f32,
+        _ => 0. ..0.,
+    };
+    (bounds(&columns, width), bounds(&rows, height))
+}
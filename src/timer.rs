@@ -0,0 +1,133 @@
+use std::{
+    collections::VecDeque,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How many frames the smoothed frame time averages over.
+const SAMPLES: usize = 30;
+/// How often the frame rate is written to the log.
+const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Measures the time between frames, and optionally holds frames back to a target rate.
+pub struct FrameTimer {
+    last_frame: Instant,
+    frame_time: Duration,
+    /// The most recent frame times, averaged to smooth out single slow or fast frames.
+    frame_times: VecDeque<Duration>,
+    /// The shortest a frame may take. Frames that finish early sleep off the rest.
+    target: Option<Duration>,
+    last_log: Instant,
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            last_frame: now,
+            frame_time: Duration::ZERO,
+            frame_times: VecDeque::with_capacity(SAMPLES),
+            target: None,
+            last_log: now,
+        }
+    }
+}
+
+impl FrameTimer {
+    /// Marks the end of a frame, first sleeping until the target frame time if there is one.
+    pub fn tick(&mut self) {
+        if let Some(remaining) = self
+            .target
+            .and_then(|target| target.checked_sub(self.last_frame.elapsed()))
+        {
+            thread::sleep(remaining);
+        }
+
+        let now = Instant::now();
+        self.frame_time = now - self.last_frame;
+        self.last_frame = now;
+        if self.frame_times.len() == SAMPLES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(self.frame_time);
+
+        if now - self.last_log >= LOG_INTERVAL {
+            self.last_log = now;
+            log::info!(
+                "{:.0} fps ({:.2} ms)",
+                self.fps(),
+                self.dt().as_secs_f64() * 1000.
+            );
+        }
+    }
+
+    /// Forgets past frames and starts timing afresh, so a pause such as being minimized
+    /// doesn't count as one very long frame.
+    pub fn reset(&mut self) {
+        self.last_frame = Instant::now();
+        self.frame_time = Duration::ZERO;
+        self.frame_times.clear();
+    }
+
+    /// How long the last frame took, from the one before it.
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+
+    /// The frame time averaged over the last few frames, for stepping the simulation.
+    pub fn dt(&self) -> Duration {
+        match self.frame_times.len() {
+            0 => Duration::ZERO,
+            len => self.frame_times.iter().sum::<Duration>() / len as u32,
+        }
+    }
+
+    /// Frames per second, averaged over the last few frames.
+    pub fn fps(&self) -> f32 {
+        let dt = self.dt();
+        if dt.is_zero() {
+            return 0.;
+        }
+        1. / dt.as_secs_f32()
+    }
+
+    pub fn target(&self) -> Option<Duration> {
+        self.target
+    }
+
+    /// Caps the frame rate by making each frame last at least `target`, or uncaps it.
+    pub fn set_target(&mut self, target: Option<Duration>) {
+        self.target = target;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_time_is_the_time_between_ticks() {
+        let mut timer = FrameTimer::default();
+        timer.tick();
+        thread::sleep(Duration::from_millis(20));
+        timer.tick();
+        assert!(timer.frame_time() >= Duration::from_millis(20));
+        // Averaged over the near-instant first frame and the slow second one
+        assert!(timer.dt() < timer.frame_time());
+        assert!(timer.fps() > 0.);
+
+        timer.reset();
+        assert_eq!(timer.frame_time(), Duration::ZERO);
+        assert_eq!(timer.dt(), Duration::ZERO);
+        assert_eq!(timer.fps(), 0.);
+    }
+
+    #[test]
+    fn target_holds_frames_back() {
+        let mut timer = FrameTimer::default();
+        timer.set_target(Some(Duration::from_millis(15)));
+        timer.tick();
+        timer.tick();
+        assert!(timer.frame_time() >= Duration::from_millis(15));
+    }
+}
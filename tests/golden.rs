@@ -0,0 +1,69 @@
+//! Renders the benchmark scene from each keyframe of its camera path and compares the
+//! frames with the reference images in assets/bench/golden. After a change that's meant to
+//! alter the picture, run with UPDATE_GOLDEN=1 to write new references, and check them in.
+
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use rust_doom::{
+    camera::{self, Camera},
+    map::Map,
+    renderer::Renderer,
+};
+use winit::dpi::PhysicalSize;
+
+const SCENE: &str = "assets/bench/scene.map";
+const SCENE_PATH: &str = "assets/bench/camera_path.txt";
+const GOLDEN_DIR: &str = "assets/bench/golden";
+const SIZE: PhysicalSize<u32> = PhysicalSize::new(160, 120);
+/// How far a channel may differ before the pixel counts as changed, and how many pixels of
+/// each frame may change, so rounding differences between machines don't fail the test.
+const CHANNEL_TOLERANCE: u8 = 8;
+const MAX_CHANGED_PIXELS: usize = 100;
+
+#[test]
+fn scene_matches_reference_frames() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let map = Map::load(root.join(SCENE)).unwrap();
+    let path = std::fs::read_to_string(root.join(SCENE_PATH)).unwrap();
+    let keyframes = camera::parse_path(&path).unwrap();
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let aspect = SIZE.width as f32 / SIZE.height as f32;
+
+    for (i, (position, direction)) in keyframes.into_iter().enumerate() {
+        let camera = Camera::new(position, direction, 0.5 * aspect);
+        let map = Rc::new(RefCell::new(map.clone()));
+        let mut renderer = Renderer::new(Rc::new(RefCell::new(camera)), map, SIZE).unwrap();
+        // Wall rays cast in fixed point come out the same on every machine
+        renderer.set_fixed_point(true);
+        // At this size the minimap would cover most of the frame
+        renderer.set_show_minimap(false);
+        renderer.render();
+
+        let golden = root.join(GOLDEN_DIR).join(format!("{i:02}.png"));
+        if update {
+            std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            renderer.save_screenshot(&golden).unwrap();
+            continue;
+        }
+        let expected = image::open(&golden)
+            .unwrap_or_else(|err| panic!("failed to open {}: {err}", golden.display()))
+            .to_rgba8();
+        assert_eq!(expected.dimensions(), (SIZE.width, SIZE.height));
+        let changed = renderer
+            .pixels()
+            .chunks_exact(4)
+            .zip(expected.pixels())
+            .filter(|(actual, expected)| {
+                actual
+                    .iter()
+                    .zip(expected.0)
+                    .any(|(&a, e)| a.abs_diff(e) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        assert!(
+            changed <= MAX_CHANGED_PIXELS,
+            "keyframe {i} differs from {} in {changed} pixels",
+            golden.display()
+        );
+    }
+}